@@ -13,8 +13,11 @@ app.get("/:hash", async (ctx) => {
 		hash,
 		"stream",
 	);
+	if (!value) throw new HTTPException(404, { message: "artwork not found" });
 
 	ctx.header("content-type", metadata.contentType);
+	// lets the app skip re-uploading artwork that will outlive the track
+	if (metadata.expiresAt) ctx.header("x-expires-at", metadata.expiresAt);
 	return ctx.body(value);
 });
 
@@ -50,7 +53,7 @@ app.put(
 		const contentType = ctx.req.header("Content-Type");
 		await ctx.env.artwork.put(hash, ctx.req.raw.body, {
 			expiration: expiresAt.toSeconds(),
-			metadata: { contentType },
+			metadata: { contentType, expiresAt: expiresAt.toUTC().toISO() },
 		});
 
 		ctx.status(201);
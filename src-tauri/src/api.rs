@@ -1,37 +1,463 @@
-use blake3::hash;
-use jiff::Timestamp;
+use std::future::Future;
 
+use anyhow::anyhow;
+use bytes::Bytes;
+use jiff::{SignedDuration, Timestamp, ToSpan};
+use reqwest::StatusCode;
+
+use crate::artwork::sniff_mime;
+
+mod ledger;
+mod local;
+mod multipart;
+mod s3;
+mod worker;
+
+pub use ledger::UploadLedger;
+pub use local::LocalHost;
+pub use multipart::MultipartHost;
+pub use s3::S3Host;
+pub use worker::WorkerHost;
+
+/// Somewhere artwork can be uploaded to so that Discord's media proxy can fetch it.
+pub trait ArtworkHost {
+	/// Uploads artwork, which must remain available until at least `expires_at`.
+	fn upload(
+		&self,
+		hash: &str,
+		mime: String,
+		bytes: Bytes,
+		expires_at: Timestamp,
+	) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+	/// When the uploaded artwork with the given hash expires, if the host knows it has it.
+	fn remote_expiry(
+		&self,
+		hash: &str,
+	) -> impl Future<Output = anyhow::Result<Option<Timestamp>>> + Send;
+
+	/// The public URL of the artwork with the given hash.
+	fn artwork_url(&self, hash: &str) -> String;
+
+	/// How far ahead of now artwork can expire, if the host limits it.
+	fn max_expiry(&self) -> Option<SignedDuration> {
+		None
+	}
+}
+
+/// The header hosts use to report when artwork expires, in response to `HEAD {url}`.
+pub const EXPIRES_AT_HEADER: &str = "x-expires-at";
+
+/// The artwork host selected in settings.
 #[derive(Debug, Clone)]
-pub struct Api {
-	pub base_url: &'static str,
-	rq: reqwest::Client,
+pub enum Api {
+	Worker(WorkerHost),
+	S3(S3Host),
+	Multipart(MultipartHost),
+	Local(LocalHost),
 }
 
 impl Api {
-	pub fn new(api_url: &'static str) -> Self {
-		Self {
-			base_url: api_url,
-			rq: reqwest::Client::new(),
+	/// Makes sure artwork is available until at least `expires_at`, only uploading it if the
+	/// ledger or the host don't already know of a copy that lives long enough.
+	///
+	/// `hash` must be the blake3 hash of `bytes`, which is computed once when artwork is first
+	/// reported. The content type is always detected from the artwork itself, and anything that
+	/// isn't an image is refused.
+	#[tracing::instrument(skip(self, ledger, bytes), err)]
+	pub async fn set_artwork(
+		&self,
+		ledger: &UploadLedger,
+		hash: &str,
+		bytes: Bytes,
+		expires_at: Timestamp,
+	) -> anyhow::Result<()> {
+		let mime =
+			sniff_mime(&bytes).ok_or(anyhow!("refusing to upload artwork that isn't an image"))?;
+		let url = self.artwork_url(hash);
+		let capped = self.clamp_expiry(expires_at);
+		// the host's cap moves with the clock, so capped copies are only replaced once they're
+		// close to expiring, rather than on every update
+		let needed = match capped < expires_at {
+			true => capped - CAPPED_REFRESH_MARGIN.minutes(),
+			false => expires_at,
+		};
+
+		let known_expiry = match ledger.expiry(&url) {
+			Some(known_expiry) => Some(known_expiry),
+			None => {
+				let remote_expiry = self.remote_expiry(hash).await.unwrap_or_else(|err| {
+					tracing::debug!(%err, "failed to check for existing artwork");
+					None
+				});
+				if let Some(remote_expiry) = remote_expiry {
+					ledger.record(url.clone(), remote_expiry).await;
+				}
+				remote_expiry
+			}
+		};
+		if known_expiry.is_some_and(|known_expiry| known_expiry >= needed) {
+			tracing::debug!(?known_expiry, "artwork already uploaded");
+			return Ok(());
+		}
+
+		// give ourselves some leeway so small seeks don't cause another upload
+		let expires_at = self.clamp_expiry(capped + EXPIRY_LEEWAY.minutes());
+		self.upload(hash, mime.to_owned(), bytes, expires_at)
+			.await?;
+		ledger.record(url, expires_at).await;
+
+		Ok(())
+	}
+
+	/// Artwork for tracks ending further ahead than the host allows expires early instead.
+	fn clamp_expiry(&self, expires_at: Timestamp) -> Timestamp {
+		match self.max_expiry() {
+			Some(max_expiry) => expires_at.min(Timestamp::now() + max_expiry),
+			None => expires_at,
 		}
 	}
+}
 
-	#[tracing::instrument(skip_all, err)]
-	pub async fn set_artwork(
+/// Minutes past the requested expiry that uploaded artwork is kept around for.
+const EXPIRY_LEEWAY: i64 = 5;
+/// Minutes before it expires that artwork capped by the host is uploaded again.
+const CAPPED_REFRESH_MARGIN: i64 = 10;
+
+impl ArtworkHost for Api {
+	async fn upload(
 		&self,
+		hash: &str,
 		mime: String,
-		bytes: Vec<u8>,
+		bytes: Bytes,
 		expires_at: Timestamp,
 	) -> anyhow::Result<()> {
-		let hash = hash(&bytes);
-		self.rq
-			.put(format!("{}/{}", self.base_url, hash))
-			.query(&[("expires_at", expires_at)])
-			.header("content-type", mime)
-			.body(bytes)
-			.send()
-			.await?
-			.error_for_status()?;
+		match self {
+			Self::Worker(host) => host.upload(hash, mime, bytes, expires_at).await,
+			Self::S3(host) => host.upload(hash, mime, bytes, expires_at).await,
+			Self::Multipart(host) => host.upload(hash, mime, bytes, expires_at).await,
+			Self::Local(host) => host.upload(hash, mime, bytes, expires_at).await,
+		}
+	}
 
-		Ok(())
+	async fn remote_expiry(&self, hash: &str) -> anyhow::Result<Option<Timestamp>> {
+		match self {
+			Self::Worker(host) => host.remote_expiry(hash).await,
+			Self::S3(host) => host.remote_expiry(hash).await,
+			Self::Multipart(host) => host.remote_expiry(hash).await,
+			Self::Local(host) => host.remote_expiry(hash).await,
+		}
+	}
+
+	fn artwork_url(&self, hash: &str) -> String {
+		match self {
+			Self::Worker(host) => host.artwork_url(hash),
+			Self::S3(host) => host.artwork_url(hash),
+			Self::Multipart(host) => host.artwork_url(hash),
+			Self::Local(host) => host.artwork_url(hash),
+		}
+	}
+
+	fn max_expiry(&self) -> Option<SignedDuration> {
+		match self {
+			Self::Worker(host) => host.max_expiry(),
+			Self::S3(host) => host.max_expiry(),
+			Self::Multipart(host) => host.max_expiry(),
+			Self::Local(host) => host.max_expiry(),
+		}
+	}
+}
+
+/// Checks whether artwork exists with a `HEAD` request, reading its expiry from the
+/// [`EXPIRES_AT_HEADER`]. Artwork that exists without a known expiry is treated as missing, since
+/// we can't tell whether it will outlive the track.
+async fn head_expiry(rq: &reqwest::Client, url: String) -> anyhow::Result<Option<Timestamp>> {
+	let res = rq.head(url).send().await?;
+	if res.status() == StatusCode::NOT_FOUND {
+		return Ok(None);
+	}
+
+	let res = res.error_for_status()?;
+	let expiry = res
+		.headers()
+		.get(EXPIRES_AT_HEADER)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.parse().ok());
+	Ok(expiry)
+}
+
+/// Joins a hash onto a base URL, regardless of whether the base has a trailing slash.
+fn join_url(base: &reqwest::Url, hash: &str) -> String {
+	format!("{}/{}", base.as_str().trim_end_matches('/'), hash)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{Arc, Mutex};
+
+	use axum::{
+		Router,
+		body::Bytes,
+		extract::{Request, State},
+		http::{HeaderMap, Method, StatusCode},
+	};
+	use jiff::{Timestamp, ToSpan};
+	use reqwest::Url;
+	use tokio::net::TcpListener;
+
+	use super::{Api, ArtworkHost, MultipartHost, S3Host, UploadLedger, WorkerHost};
+	use crate::testing::PNG;
+
+	#[derive(Debug, Clone)]
+	struct Recorded {
+		method: Method,
+		path: String,
+		query: Option<String>,
+		headers: HeaderMap,
+		body: Bytes,
+	}
+
+	type Requests = Arc<Mutex<Vec<Recorded>>>;
+
+	/// Starts a local HTTP server that records every request it receives.
+	async fn stand_in() -> (Url, Requests) {
+		let requests = Requests::default();
+
+		async fn record(State(requests): State<Requests>, request: Request) -> StatusCode {
+			let (parts, body) = request.into_parts();
+			let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+			requests.lock().unwrap().push(Recorded {
+				method: parts.method,
+				path: parts.uri.path().to_owned(),
+				query: parts.uri.query().map(ToOwned::to_owned),
+				headers: parts.headers,
+				body,
+			});
+			StatusCode::CREATED
+		}
+
+		let app = Router::new().fallback(record).with_state(requests.clone());
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+		(format!("http://{addr}/").parse().unwrap(), requests)
+	}
+
+	#[tokio::test]
+	async fn refuses_non_images() {
+		let (url, requests) = stand_in().await;
+		let api = Api::Worker(WorkerHost::new(url, None));
+
+		let html = Bytes::from_static(b"<html>");
+		let hash = blake3::hash(&html).to_hex();
+		let result = api
+			.set_artwork(&UploadLedger::default(), &hash, html, expires_at())
+			.await;
+
+		assert!(result.is_err());
+		assert!(requests.lock().unwrap().is_empty());
+	}
+
+	fn expires_at() -> Timestamp {
+		"2025-01-01T00:00:00Z".parse().unwrap()
+	}
+
+	#[tokio::test]
+	async fn worker_puts_to_hash() {
+		let (url, requests) = stand_in().await;
+		let host = WorkerHost::new(url.clone(), Some("hunter2".into()));
+
+		host.upload(
+			"abc",
+			"image/png".into(),
+			Bytes::from_static(&[1, 2, 3]),
+			expires_at(),
+		)
+		.await
+		.unwrap();
+
+		let requests = requests.lock().unwrap();
+		let [request] = requests.as_slice() else {
+			panic!("expected one request, got {requests:?}");
+		};
+		assert_eq!(request.method, Method::PUT);
+		assert_eq!(request.path, "/abc");
+		assert_eq!(
+			request.query.as_deref(),
+			Some("expires_at=2025-01-01T00%3A00%3A00Z")
+		);
+		assert_eq!(request.headers["content-type"], "image/png");
+		assert_eq!(request.headers["authorization"], "Bearer hunter2");
+		assert_eq!(&request.body[..], [1, 2, 3]);
+		assert_eq!(host.artwork_url("abc"), format!("{url}abc"));
+	}
+
+	#[tokio::test]
+	async fn skips_uploads_recorded_in_ledger() {
+		let (url, requests) = stand_in().await;
+		let api = Api::Worker(WorkerHost::new(url, None));
+		let ledger = UploadLedger::default();
+		let methods = || {
+			requests
+				.lock()
+				.unwrap()
+				.iter()
+				.map(|request| request.method.clone())
+				.collect::<Vec<_>>()
+		};
+
+		let hash = blake3::hash(PNG).to_hex();
+		let png = Bytes::from_static(PNG);
+
+		let expires_at = Timestamp::now() + 10.minutes();
+		api.set_artwork(&ledger, &hash, png.clone(), expires_at)
+			.await
+			.unwrap();
+		assert_eq!(methods(), [Method::HEAD, Method::PUT]);
+
+		api.set_artwork(&ledger, &hash, png.clone(), expires_at)
+			.await
+			.unwrap();
+		assert_eq!(methods(), [Method::HEAD, Method::PUT]);
+
+		let expires_at = expires_at + 1.hour();
+		api.set_artwork(&ledger, &hash, png.clone(), expires_at)
+			.await
+			.unwrap();
+		assert_eq!(methods(), [Method::HEAD, Method::PUT, Method::PUT]);
+	}
+
+	#[tokio::test]
+	async fn clamps_expiry_to_the_worker_limit() {
+		let (url, requests) = stand_in().await;
+		let api = Api::Worker(WorkerHost::new(url, None));
+
+		let hash = blake3::hash(PNG).to_hex();
+		let expires_at = Timestamp::now() + 3.hours();
+		api.set_artwork(
+			&UploadLedger::default(),
+			&hash,
+			Bytes::from_static(PNG),
+			expires_at,
+		)
+		.await
+		.unwrap();
+
+		let requests = requests.lock().unwrap();
+		let put = requests
+			.iter()
+			.find(|request| request.method == Method::PUT)
+			.unwrap();
+		let query = format!("http://localhost/?{}", put.query.as_deref().unwrap());
+		let query = Url::parse(&query).unwrap();
+		let (_, uploaded_expiry) = query
+			.query_pairs()
+			.find(|(name, _)| name == "expires_at")
+			.unwrap();
+		let uploaded_expiry: Timestamp = uploaded_expiry.parse().unwrap();
+		assert!(uploaded_expiry < Timestamp::now() + 2.hours());
+		assert!(uploaded_expiry > Timestamp::now() + 1.hour());
+	}
+
+	#[tokio::test]
+	async fn keeps_capped_uploads_until_close_to_expiring() {
+		let (url, requests) = stand_in().await;
+		let api = Api::Worker(WorkerHost::new(url, None));
+		let ledger = UploadLedger::default();
+
+		let hash = blake3::hash(PNG).to_hex();
+		let png = Bytes::from_static(PNG);
+		let expires_at = Timestamp::now() + 3.hours();
+		for _ in 0..2 {
+			api.set_artwork(&ledger, &hash, png.clone(), expires_at)
+				.await
+				.unwrap();
+		}
+
+		let methods: Vec<_> = requests
+			.lock()
+			.unwrap()
+			.iter()
+			.map(|request| request.method.clone())
+			.collect();
+		assert_eq!(methods, [Method::HEAD, Method::PUT]);
+	}
+
+	#[tokio::test]
+	async fn s3_puts_presigned() {
+		let (url, requests) = stand_in().await;
+		let host = S3Host::new(
+			url,
+			"artwork".into(),
+			"auto".into(),
+			"key-id".into(),
+			"secret".into(),
+			"https://cdn.example.com/artwork".parse().unwrap(),
+		);
+
+		host.upload(
+			"abc",
+			"image/jpeg".into(),
+			Bytes::from_static(&[4, 5]),
+			expires_at(),
+		)
+		.await
+		.unwrap();
+		// nothing to ask the host, so no HEAD request either
+		assert_eq!(host.remote_expiry("abc").await.unwrap(), None);
+
+		let requests = requests.lock().unwrap();
+		let [request] = requests.as_slice() else {
+			panic!("expected one request, got {requests:?}");
+		};
+		assert_eq!(request.method, Method::PUT);
+		assert_eq!(request.path, "/artwork/abc");
+		let query = request.query.as_deref().unwrap();
+		assert!(query.contains("X-Amz-Algorithm=AWS4-HMAC-SHA256"));
+		assert!(query.contains("X-Amz-Credential=key-id%2F"));
+		assert!(query.contains("X-Amz-Signature="));
+		assert_eq!(request.headers["content-type"], "image/jpeg");
+		assert_eq!(&request.body[..], [4, 5]);
+		assert_eq!(
+			host.artwork_url("abc"),
+			"https://cdn.example.com/artwork/abc"
+		);
+	}
+
+	#[tokio::test]
+	async fn multipart_posts_form() {
+		let (url, requests) = stand_in().await;
+		let host = MultipartHost::new(
+			url.join("upload").unwrap(),
+			"file".into(),
+			"https://cdn.example.com/".parse().unwrap(),
+		);
+
+		host.upload(
+			"abc",
+			"image/png".into(),
+			Bytes::from_static(&[6, 7, 8]),
+			expires_at(),
+		)
+		.await
+		.unwrap();
+		// nothing to ask the host, so no HEAD request either
+		assert_eq!(host.remote_expiry("abc").await.unwrap(), None);
+
+		let requests = requests.lock().unwrap();
+		let [request] = requests.as_slice() else {
+			panic!("expected one request, got {requests:?}");
+		};
+		assert_eq!(request.method, Method::POST);
+		assert_eq!(request.path, "/upload");
+		let content_type = request.headers["content-type"].to_str().unwrap();
+		assert!(content_type.starts_with("multipart/form-data; boundary="));
+		let body = String::from_utf8_lossy(&request.body);
+		assert!(body.contains(r#"name="file"; filename="abc""#));
+		assert!(body.contains("Content-Type: image/png"));
+		assert!(body.contains(r#"name="expires_at""#));
+		assert!(body.contains("2025-01-01T00:00:00Z"));
+		assert_eq!(host.artwork_url("abc"), "https://cdn.example.com/abc");
 	}
 }
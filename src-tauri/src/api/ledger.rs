@@ -0,0 +1,81 @@
+use std::{
+	collections::HashMap,
+	io,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+use jiff::Timestamp;
+use tokio::fs;
+use tracing::Level;
+
+/// Remembers which artwork URLs have been uploaded and until when they're available, so the same
+/// artwork isn't uploaded again for every track of an album or every timeline update.
+#[derive(Debug, Clone, Default)]
+pub struct UploadLedger {
+	entries: Arc<Mutex<HashMap<String, Timestamp>>>,
+	path: Option<PathBuf>,
+}
+
+impl UploadLedger {
+	/// Loads the ledger persisted at `path`, starting empty if it doesn't exist or can't be read.
+	pub fn load(path: PathBuf) -> Self {
+		let entries = std::fs::read(&path)
+			.ok()
+			.and_then(|bytes| serde_json::from_slice::<HashMap<String, Timestamp>>(&bytes).ok())
+			.unwrap_or_default();
+
+		let ledger = Self {
+			entries: Arc::new(Mutex::new(entries)),
+			path: Some(path),
+		};
+		ledger.prune();
+		ledger
+	}
+
+	/// When the artwork at `url` is known to expire, if it's known to have been uploaded.
+	pub fn expiry(&self, url: &str) -> Option<Timestamp> {
+		self.entries
+			.lock()
+			.unwrap()
+			.get(url)
+			.copied()
+			.filter(|expires_at| *expires_at > Timestamp::now())
+	}
+
+	#[tracing::instrument(skip(self), level = Level::DEBUG)]
+	pub async fn record(&self, url: String, expires_at: Timestamp) {
+		let serialized = {
+			let mut entries = self.entries.lock().unwrap();
+			let existing = entries.entry(url).or_insert(expires_at);
+			*existing = expires_at.max(*existing);
+
+			let now = Timestamp::now();
+			entries.retain(|_, expires_at| *expires_at > now);
+			serde_json::to_vec(&*entries)
+		};
+
+		let Some(path) = &self.path else {
+			return;
+		};
+		// the ledger is only an optimization, so failing to persist it isn't worth failing over
+		if let Err(err) = persist(path, serialized).await {
+			tracing::warn!(%err, "failed to persist upload ledger");
+		}
+	}
+
+	fn prune(&self) {
+		let now = Timestamp::now();
+		self.entries
+			.lock()
+			.unwrap()
+			.retain(|_, expires_at| *expires_at > now);
+	}
+}
+
+async fn persist(path: &Path, serialized: serde_json::Result<Vec<u8>>) -> io::Result<()> {
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir).await?;
+	}
+	fs::write(path, serialized?).await
+}
@@ -0,0 +1,48 @@
+use bytes::Bytes;
+use jiff::Timestamp;
+use reqwest::Url;
+
+use super::{ArtworkHost, join_url};
+use crate::server::{ArtworkStore, StoredArtwork};
+
+/// The embedded artwork server, exposed publicly at `public_url` through a reverse proxy or
+/// tunnel.
+#[derive(Debug, Clone)]
+pub struct LocalHost {
+	store: ArtworkStore,
+	public_url: Url,
+}
+
+impl LocalHost {
+	pub fn new(store: ArtworkStore, public_url: Url) -> Self {
+		Self { store, public_url }
+	}
+}
+
+impl ArtworkHost for LocalHost {
+	#[tracing::instrument(skip(self, bytes), err)]
+	async fn upload(
+		&self,
+		hash: &str,
+		mime: String,
+		bytes: Bytes,
+		expires_at: Timestamp,
+	) -> anyhow::Result<()> {
+		let artwork = StoredArtwork {
+			mime,
+			expires_at,
+			bytes,
+		};
+		self.store.insert(hash.to_owned(), artwork).await?;
+
+		Ok(())
+	}
+
+	async fn remote_expiry(&self, hash: &str) -> anyhow::Result<Option<Timestamp>> {
+		Ok(self.store.get(hash).await.map(|artwork| artwork.expires_at))
+	}
+
+	fn artwork_url(&self, hash: &str) -> String {
+		join_url(&self.public_url, hash)
+	}
+}
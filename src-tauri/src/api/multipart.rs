@@ -0,0 +1,67 @@
+use bytes::Bytes;
+use jiff::Timestamp;
+use reqwest::{
+	Url,
+	multipart::{Form, Part},
+};
+
+use super::{ArtworkHost, join_url};
+
+/// A generic upload endpoint which accepts artwork as a `multipart/form-data` POST, alongside
+/// `hash` and `expires_at` text fields, and serves it from `{public_url}/{hash}`.
+#[derive(Debug, Clone)]
+pub struct MultipartHost {
+	upload_url: Url,
+	field: String,
+	public_url: Url,
+	rq: reqwest::Client,
+}
+
+impl MultipartHost {
+	pub fn new(upload_url: Url, field: String, public_url: Url) -> Self {
+		Self {
+			upload_url,
+			field,
+			public_url,
+			rq: reqwest::Client::new(),
+		}
+	}
+}
+
+impl ArtworkHost for MultipartHost {
+	#[tracing::instrument(skip(self, bytes), err)]
+	async fn upload(
+		&self,
+		hash: &str,
+		mime: String,
+		bytes: Bytes,
+		expires_at: Timestamp,
+	) -> anyhow::Result<()> {
+		let file = Part::stream(bytes)
+			.file_name(hash.to_owned())
+			.mime_str(&mime)?;
+		let form = Form::new()
+			.text("hash", hash.to_owned())
+			.text("expires_at", expires_at.to_string())
+			.part(self.field.clone(), file);
+
+		self.rq
+			.post(self.upload_url.clone())
+			.multipart(form)
+			.send()
+			.await?
+			.error_for_status()?;
+
+		Ok(())
+	}
+
+	async fn remote_expiry(&self, _hash: &str) -> anyhow::Result<Option<Timestamp>> {
+		// artwork is usually served from a bucket or CDN, which can't report when it expires, so
+		// asking would only cost a request
+		Ok(None)
+	}
+
+	fn artwork_url(&self, hash: &str) -> String {
+		join_url(&self.public_url, hash)
+	}
+}
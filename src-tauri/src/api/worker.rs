@@ -0,0 +1,77 @@
+use std::fmt;
+
+use bytes::Bytes;
+use jiff::{SignedDuration, Timestamp};
+use reqwest::Url;
+
+use super::{ArtworkHost, head_expiry, join_url};
+use crate::{server::MAX_EXPIRY, settings::Redacted};
+
+/// The worker and the built-in artwork server reject artwork expiring [`MAX_EXPIRY`] or more from
+/// now by their own clocks, so uploads stay a little under that.
+const CLOCK_SKEW: SignedDuration = SignedDuration::from_mins(1);
+
+/// The Cloudflare worker in `/api`, which stores artwork in KV until `expires_at`. The built-in
+/// artwork server of another install implements the same API, and requires a `token`.
+#[derive(Clone)]
+pub struct WorkerHost {
+	base_url: Url,
+	token: Option<String>,
+	rq: reqwest::Client,
+}
+
+impl fmt::Debug for WorkerHost {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("WorkerHost")
+			.field("base_url", &self.base_url)
+			.field("token", &self.token.as_ref().map(|_| Redacted))
+			.finish_non_exhaustive()
+	}
+}
+
+impl WorkerHost {
+	pub fn new(base_url: Url, token: Option<String>) -> Self {
+		Self {
+			base_url,
+			token,
+			rq: reqwest::Client::new(),
+		}
+	}
+}
+
+impl ArtworkHost for WorkerHost {
+	#[tracing::instrument(skip(self, bytes), err)]
+	async fn upload(
+		&self,
+		hash: &str,
+		mime: String,
+		bytes: Bytes,
+		expires_at: Timestamp,
+	) -> anyhow::Result<()> {
+		let mut request = self
+			.rq
+			.put(self.artwork_url(hash))
+			.query(&[("expires_at", expires_at)])
+			.header("content-type", mime)
+			.body(bytes);
+		if let Some(token) = &self.token {
+			request = request.bearer_auth(token);
+		}
+
+		request.send().await?.error_for_status()?;
+
+		Ok(())
+	}
+
+	async fn remote_expiry(&self, hash: &str) -> anyhow::Result<Option<Timestamp>> {
+		head_expiry(&self.rq, self.artwork_url(hash)).await
+	}
+
+	fn artwork_url(&self, hash: &str) -> String {
+		join_url(&self.base_url, hash)
+	}
+
+	fn max_expiry(&self) -> Option<SignedDuration> {
+		Some(MAX_EXPIRY - CLOCK_SKEW)
+	}
+}
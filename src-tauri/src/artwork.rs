@@ -0,0 +1,367 @@
+use std::{borrow::Cow, io::Cursor, sync::Mutex};
+
+use anyhow::{Context, bail};
+use image::{
+	DynamicImage, ImageDecoder, ImageReader,
+	codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+	imageops::FilterType,
+	metadata::Orientation,
+};
+use serde::{Deserialize, Serialize};
+use tracing::Level;
+
+use crate::media::Artwork;
+
+mod cache;
+mod sanitize;
+
+pub use cache::{ArtworkCache, is_hash};
+pub use sanitize::strip_metadata;
+
+/// Artwork smaller than this isn't worth shrinking any further.
+const MIN_DIMENSION: u32 = 64;
+const JPEG_QUALITIES: [u8; 5] = [90, 80, 70, 60, 50];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessingSettings {
+	/// Artwork is downscaled to fit within a square of this size.
+	pub max_dimension: u32,
+	/// Artwork is re-encoded until it's at most this many bytes. The worker rejects anything
+	/// over 500 KB.
+	pub max_bytes: usize,
+	/// The format artwork is re-encoded to when it needs processing.
+	pub format: OutputFormat,
+}
+
+impl Default for ProcessingSettings {
+	fn default() -> Self {
+		Self {
+			max_dimension: 512,
+			max_bytes: 500 * 1024,
+			format: OutputFormat::Jpeg,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+	Png,
+	Jpeg,
+	/// Lossless WebP.
+	Webp,
+}
+
+impl OutputFormat {
+	fn mime(self) -> &'static str {
+		match self {
+			Self::Png => "image/png",
+			Self::Jpeg => "image/jpeg",
+			Self::Webp => "image/webp",
+		}
+	}
+}
+
+/// Detects the format of an image from its magic bytes, since players often misreport it or
+/// don't report it at all. Returns `None` unless the bytes are a PNG, JPEG or WebP image, the
+/// only formats `image` is built to decode.
+pub fn sniff_mime(bytes: &[u8]) -> Option<&'static str> {
+	match bytes {
+		[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Some("image/png"),
+		[0xff, 0xd8, 0xff, ..] => Some("image/jpeg"),
+		[
+			b'R',
+			b'I',
+			b'F',
+			b'F',
+			_,
+			_,
+			_,
+			_,
+			b'W',
+			b'E',
+			b'B',
+			b'P',
+			..,
+		] => Some("image/webp"),
+		_ => None,
+	}
+}
+
+/// Strips metadata from artwork and downscales and re-encodes artwork that's too big. Artwork
+/// that's already within the limits is otherwise left untouched.
+#[tracing::instrument(
+	skip_all,
+	fields(hash = %artwork.hash, len = artwork.bytes.len()),
+	err,
+	level = Level::DEBUG
+)]
+pub fn process(artwork: &Artwork, settings: &ProcessingSettings) -> anyhow::Result<Artwork> {
+	let bytes = &artwork.bytes;
+	let mut decoder = ImageReader::new(Cursor::new(bytes))
+		.with_guessed_format()?
+		.into_decoder()?;
+	let (width, height) = decoder.dimensions();
+	// stripping metadata drops the EXIF orientation, so rotated artwork is re-encoded upright
+	let orientation = decoder.orientation()?;
+
+	// re-encoding drops metadata too, so we only need to strip it from artwork we pass through
+	let stripped = match strip_metadata(bytes) {
+		Ok(stripped) => Some(stripped),
+		Err(err) => {
+			tracing::debug!(%err, "failed to strip metadata, re-encoding instead");
+			None
+		}
+	};
+
+	if let Some(stripped) = stripped
+		&& orientation == Orientation::NoTransforms
+		&& stripped.len() <= settings.max_bytes
+		&& width <= settings.max_dimension
+		&& height <= settings.max_dimension
+	{
+		// only hash again if stripping changed anything
+		return Ok(match stripped {
+			Cow::Borrowed(_) => artwork.clone(),
+			Cow::Owned(stripped) => Artwork::new(artwork.mime.clone(), stripped.into()),
+		});
+	}
+
+	let mut image = DynamicImage::from_decoder(decoder).context("failed to decode artwork")?;
+	image.apply_orientation(orientation);
+
+	let mut dimension = settings.max_dimension.max(MIN_DIMENSION);
+	loop {
+		let resized = if image.width() > dimension || image.height() > dimension {
+			image.resize(dimension, dimension, FilterType::CatmullRom)
+		} else {
+			image.clone()
+		};
+
+		if let Some(bytes) = encode_within(&resized, settings.format, settings.max_bytes)? {
+			return Ok(Artwork::new(
+				settings.format.mime().to_owned(),
+				bytes.into(),
+			));
+		}
+
+		if dimension <= MIN_DIMENSION {
+			bail!(
+				"artwork can't be encoded within {} bytes",
+				settings.max_bytes
+			);
+		}
+		dimension = (dimension / 2).max(MIN_DIMENSION);
+	}
+}
+
+/// Encodes the image, lowering the quality of lossy formats until it fits within `max_bytes`.
+fn encode_within(
+	image: &DynamicImage,
+	format: OutputFormat,
+	max_bytes: usize,
+) -> anyhow::Result<Option<Vec<u8>>> {
+	let fits = |bytes: Vec<u8>| (bytes.len() <= max_bytes).then_some(bytes);
+
+	match format {
+		OutputFormat::Png => {
+			let mut bytes = Vec::new();
+			image.write_with_encoder(PngEncoder::new(&mut bytes))?;
+			Ok(fits(bytes))
+		}
+		OutputFormat::Webp => {
+			let mut bytes = Vec::new();
+			// the encoder only supports 8-bit RGB(A)
+			DynamicImage::from(image.to_rgba8())
+				.write_with_encoder(WebPEncoder::new_lossless(&mut bytes))?;
+			Ok(fits(bytes))
+		}
+		OutputFormat::Jpeg => {
+			// JPEG has no alpha channel
+			let image = DynamicImage::from(image.to_rgb8());
+			for quality in JPEG_QUALITIES {
+				let mut bytes = Vec::new();
+				image.write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, quality))?;
+				if let Some(bytes) = fits(bytes) {
+					return Ok(Some(bytes));
+				}
+			}
+			Ok(None)
+		}
+	}
+}
+
+/// Remembers the last processed artwork, since backends report the same artwork on every
+/// timeline update, and reuses artwork processed in previous runs from the cache.
+#[derive(Default)]
+pub struct ArtworkProcessor {
+	last: Mutex<Option<(String, ProcessingSettings, Artwork)>>,
+}
+
+impl ArtworkProcessor {
+	pub fn process(
+		&self,
+		artwork: &Artwork,
+		settings: &ProcessingSettings,
+		cache: &ArtworkCache,
+	) -> anyhow::Result<Artwork> {
+		if let Some((hash, last_settings, processed)) = &*self.last.lock().unwrap()
+			&& *hash == artwork.hash
+			&& last_settings == settings
+		{
+			return Ok(processed.clone());
+		}
+
+		let processed = match cache.processed(&artwork.hash, settings) {
+			Some(processed) => processed,
+			None => {
+				let processed = process(artwork, settings)?;
+				cache.insert_processed(&artwork.hash, settings, processed.clone());
+				processed
+			}
+		};
+		*self.last.lock().unwrap() =
+			Some((artwork.hash.clone(), settings.clone(), processed.clone()));
+		Ok(processed)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use bytes::Bytes;
+	use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage};
+
+	use super::{OutputFormat, ProcessingSettings, process, sniff_mime};
+	use crate::media::Artwork;
+
+	/// A noisy image, which compresses badly, so encodes to something large.
+	fn noise(width: u32, height: u32) -> DynamicImage {
+		let mut state = 0x2545_f491_u32;
+		DynamicImage::from(RgbImage::from_fn(width, height, |_, _| {
+			let mut channel = || {
+				// xorshift
+				state ^= state << 13;
+				state ^= state >> 17;
+				state ^= state << 5;
+				state as u8
+			};
+			image::Rgb([channel(), channel(), channel()])
+		}))
+	}
+
+	fn format_of(bytes: &[u8]) -> Option<ImageFormat> {
+		image::guess_format(bytes).ok()
+	}
+
+	fn encode(image: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+		let mut bytes = Cursor::new(Vec::new());
+		image.write_to(&mut bytes, format).unwrap();
+		bytes.into_inner()
+	}
+
+	fn png(image: &DynamicImage) -> Artwork {
+		Artwork::new("image/png".into(), encode(image, ImageFormat::Png).into())
+	}
+
+	#[test]
+	fn leaves_small_artwork_untouched() {
+		let artwork = png(&noise(100, 100));
+		let processed = process(&artwork, &ProcessingSettings::default()).unwrap();
+
+		assert_eq!(processed.bytes, artwork.bytes);
+		assert_eq!(processed.mime, "image/png");
+		assert_eq!(processed.hash, artwork.hash);
+	}
+
+	#[test]
+	fn shrinks_large_artwork_within_budget() {
+		let artwork = png(&noise(1500, 1000));
+		let settings = ProcessingSettings::default();
+		assert!(artwork.bytes.len() > settings.max_bytes);
+
+		let processed = process(&artwork, &settings).unwrap();
+
+		assert!(processed.bytes.len() <= settings.max_bytes);
+		assert_eq!(processed.mime, "image/jpeg");
+		assert_eq!(format_of(&processed.bytes), Some(ImageFormat::Jpeg));
+		assert_eq!(
+			processed.hash,
+			blake3::hash(&processed.bytes).to_hex().as_str()
+		);
+
+		let image = image::load_from_memory(&processed.bytes).unwrap();
+		assert_eq!((image.width(), image.height()), (512, 341));
+	}
+
+	#[test]
+	fn downscales_lossless_formats_until_within_budget() {
+		let artwork = png(&noise(1024, 1024));
+		let settings = ProcessingSettings {
+			max_bytes: 100 * 1024,
+			format: OutputFormat::Webp,
+			..Default::default()
+		};
+
+		let processed = process(&artwork, &settings).unwrap();
+
+		assert!(processed.bytes.len() <= settings.max_bytes);
+		assert_eq!(format_of(&processed.bytes), Some(ImageFormat::WebP));
+		let image = image::load_from_memory(&processed.bytes).unwrap();
+		assert!(image.width() < 512);
+	}
+
+	#[test]
+	fn flattens_transparency_for_jpeg() {
+		let image = DynamicImage::from(RgbaImage::from_pixel(
+			1024,
+			1024,
+			image::Rgba([255, 0, 0, 128]),
+		));
+		let processed = process(&png(&image), &ProcessingSettings::default()).unwrap();
+
+		assert_eq!(format_of(&processed.bytes), Some(ImageFormat::Jpeg));
+		let image = image::load_from_memory(&processed.bytes).unwrap();
+		assert_eq!((image.width(), image.height()), (512, 512));
+	}
+
+	#[test]
+	fn sniffs_actual_format() {
+		let png = encode(&noise(8, 8), ImageFormat::Png);
+		let jpeg = encode(&noise(8, 8), ImageFormat::Jpeg);
+		let webp = encode(&noise(8, 8), ImageFormat::WebP);
+
+		assert_eq!(sniff_mime(&png), Some("image/png"));
+		assert_eq!(sniff_mime(&jpeg), Some("image/jpeg"));
+		assert_eq!(sniff_mime(&webp), Some("image/webp"));
+		assert_eq!(sniff_mime(b"GIF89a\x01\x00"), None);
+		assert_eq!(sniff_mime(b"BM<html>"), None);
+		assert_eq!(sniff_mime(b"<html>"), None);
+		assert_eq!(sniff_mime(b""), None);
+	}
+
+	#[test]
+	fn rotates_artwork_by_its_orientation() {
+		let jpeg = encode(&noise(40, 20), ImageFormat::Jpeg);
+		// a big-endian EXIF segment holding only an orientation of 6, a 90° clockwise rotation
+		let exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0";
+		let mut rotated = jpeg[..2].to_vec();
+		rotated.extend_from_slice(&[0xff, 0xe1, 0, exif.len() as u8 + 2]);
+		rotated.extend_from_slice(exif);
+		rotated.extend_from_slice(&jpeg[2..]);
+		let artwork = Artwork::new("image/jpeg".into(), rotated.into());
+
+		let processed = process(&artwork, &ProcessingSettings::default()).unwrap();
+
+		let image = image::load_from_memory(&processed.bytes).unwrap();
+		assert_eq!((image.width(), image.height()), (20, 40));
+	}
+
+	#[test]
+	fn rejects_non_images() {
+		let artwork = Artwork::new("image/png".into(), Bytes::from_static(b"not an image"));
+		assert!(process(&artwork, &ProcessingSettings::default()).is_err());
+	}
+}
@@ -0,0 +1,318 @@
+use std::{
+	fs::{self, OpenOptions},
+	io,
+	num::NonZeroUsize,
+	path::{Path, PathBuf},
+	sync::Mutex,
+	time::{Duration, SystemTime},
+};
+
+use lru::LruCache;
+use tracing::Level;
+
+use super::{ProcessingSettings, sniff_mime};
+use crate::media::Artwork;
+
+/// Enough for the current artwork and some recent history, without holding on to every cover
+/// that's ever played.
+const CAPACITY: NonZeroUsize = NonZeroUsize::new(32).unwrap();
+const MAX_DISK_BYTES: u64 = 64 * 1024 * 1024;
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+const ALIAS_EXTENSION: &str = "alias";
+
+/// Recently prepared artwork, keyed by hash, so it can be served to the webview and uploaded
+/// without sending the bytes back and forth over IPC.
+///
+/// Artwork is also written through to disk, where it's kept across restarts until it's evicted
+/// for being too old or least recently used once the cache is full. Disk access is blocking, so
+/// callers should be on a blocking thread.
+pub struct ArtworkCache {
+	memory: Mutex<LruCache<String, Artwork>>,
+	disk: Option<DiskCache>,
+}
+
+struct DiskCache {
+	dir: PathBuf,
+	max_bytes: u64,
+	max_age: Duration,
+}
+
+impl Default for ArtworkCache {
+	fn default() -> Self {
+		Self::new(None)
+	}
+}
+
+impl ArtworkCache {
+	pub fn new(dir: Option<PathBuf>) -> Self {
+		Self {
+			memory: Mutex::new(LruCache::new(CAPACITY)),
+			disk: dir.map(|dir| DiskCache {
+				dir,
+				max_bytes: MAX_DISK_BYTES,
+				max_age: MAX_AGE,
+			}),
+		}
+	}
+
+	pub fn insert(&self, artwork: Artwork) {
+		if let Some(disk) = &self.disk
+			&& let Err(err) = disk.insert(&artwork)
+		{
+			tracing::warn!(%err, hash = %artwork.hash, "failed to cache artwork on disk");
+		}
+
+		self.memory
+			.lock()
+			.unwrap()
+			.put(artwork.hash.clone(), artwork);
+	}
+
+	pub fn get(&self, hash: &str) -> Option<Artwork> {
+		if let Some(artwork) = self.get_in_memory(hash) {
+			return Some(artwork);
+		}
+
+		let artwork = self.disk.as_ref()?.get(hash)?;
+		self.memory
+			.lock()
+			.unwrap()
+			.put(hash.to_owned(), artwork.clone());
+		Some(artwork)
+	}
+
+	/// Gets artwork without touching the disk, for callers that can't block.
+	pub fn get_in_memory(&self, hash: &str) -> Option<Artwork> {
+		self.memory.lock().unwrap().get(hash).cloned()
+	}
+
+	/// Artwork previously processed from the artwork with `source_hash` using `settings`.
+	pub fn processed(&self, source_hash: &str, settings: &ProcessingSettings) -> Option<Artwork> {
+		let hash = self
+			.disk
+			.as_ref()?
+			.alias(&alias_key(source_hash, settings))?;
+		self.get(&hash)
+	}
+
+	pub fn insert_processed(
+		&self,
+		source_hash: &str,
+		settings: &ProcessingSettings,
+		processed: Artwork,
+	) {
+		if let Some(disk) = &self.disk
+			&& let Err(err) = disk.insert_alias(&alias_key(source_hash, settings), &processed.hash)
+		{
+			tracing::warn!(%err, "failed to cache processed artwork alias");
+		}
+		self.insert(processed);
+	}
+
+	/// Removes artwork from disk that's too old, then the least recently used artwork until the
+	/// cache fits within its size limit.
+	#[tracing::instrument(skip(self), level = Level::DEBUG)]
+	pub fn evict(&self) {
+		if let Some(disk) = &self.disk
+			&& let Err(err) = disk.evict()
+		{
+			tracing::warn!(%err, "failed to evict cached artwork");
+		}
+	}
+}
+
+impl DiskCache {
+	fn insert(&self, artwork: &Artwork) -> io::Result<()> {
+		let path = self.dir.join(&artwork.hash);
+		if path.exists() {
+			return touch(&path);
+		}
+
+		write(&self.dir, &path, &artwork.bytes)?;
+		self.evict()
+	}
+
+	fn get(&self, hash: &str) -> Option<Artwork> {
+		if !is_hash(hash) {
+			return None;
+		}
+
+		let path = self.dir.join(hash);
+		let bytes = fs::read(&path).ok()?;
+		let mime = sniff_mime(&bytes)?;
+		let _ = touch(&path);
+		Some(Artwork {
+			mime: mime.to_owned(),
+			bytes: bytes.into(),
+			hash: hash.to_owned(),
+		})
+	}
+
+	fn alias(&self, key: &str) -> Option<String> {
+		let path = self.dir.join(key).with_extension(ALIAS_EXTENSION);
+		let hash = fs::read_to_string(&path).ok()?;
+		let _ = touch(&path);
+		is_hash(&hash).then_some(hash)
+	}
+
+	fn insert_alias(&self, key: &str, hash: &str) -> io::Result<()> {
+		let path = self.dir.join(key).with_extension(ALIAS_EXTENSION);
+		write(&self.dir, &path, hash.as_bytes())
+	}
+
+	fn evict(&self) -> io::Result<()> {
+		let now = SystemTime::now();
+		let mut files = Vec::new();
+		for entry in fs::read_dir(&self.dir)? {
+			let entry = entry?;
+			let metadata = entry.metadata()?;
+			if !metadata.is_file() {
+				continue;
+			}
+
+			let used = metadata.modified()?;
+			if now.duration_since(used).unwrap_or_default() >= self.max_age {
+				fs::remove_file(entry.path())?;
+			} else {
+				files.push((used, metadata.len(), entry.path()));
+			}
+		}
+
+		let mut total = files.iter().map(|(_, len, _)| len).sum::<u64>();
+		files.sort_unstable_by_key(|(used, _, _)| *used);
+		for (_, len, path) in files {
+			if total <= self.max_bytes {
+				break;
+			}
+			fs::remove_file(path)?;
+			total -= len;
+		}
+
+		Ok(())
+	}
+}
+
+/// Processed artwork depends on both the source artwork and the settings it was processed with.
+fn alias_key(source_hash: &str, settings: &ProcessingSettings) -> String {
+	let mut hasher = blake3::Hasher::new();
+	hasher.update(source_hash.as_bytes());
+	hasher.update(&serde_json::to_vec(settings).unwrap_or_default());
+	hasher.finalize().to_hex().to_string()
+}
+
+/// Hashes come from the webview and the artwork server's clients, so they have to be checked
+/// before they're used as paths.
+pub fn is_hash(hash: &str) -> bool {
+	hash.len() == blake3::OUT_LEN * 2 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Writes to a temporary file first, so a crash can't leave partially written artwork behind.
+fn write(dir: &Path, path: &Path, contents: &[u8]) -> io::Result<()> {
+	fs::create_dir_all(dir)?;
+	let tmp = path.with_extension("tmp");
+	fs::write(&tmp, contents)?;
+	fs::rename(tmp, path)
+}
+
+/// Marks a file as recently used, which is what eviction goes by.
+fn touch(path: &Path) -> io::Result<()> {
+	OpenOptions::new()
+		.write(true)
+		.open(path)?
+		.set_modified(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{fs, thread::sleep, time::Duration};
+
+	use bytes::Bytes;
+
+	use super::{ArtworkCache, CAPACITY};
+	use crate::{
+		artwork::ProcessingSettings,
+		media::Artwork,
+		testing::{PNG, TempDir},
+	};
+
+	fn png(n: u8) -> Artwork {
+		let mut bytes = PNG.to_vec();
+		bytes.extend([n; 100]);
+		Artwork::new("image/png".into(), bytes.into())
+	}
+
+	#[test]
+	fn evicts_least_recently_used() {
+		let cache = ArtworkCache::default();
+		let first = Artwork::new("image/png".into(), Bytes::from_static(&[0]));
+		cache.insert(first.clone());
+
+		for i in 1..CAPACITY.get() {
+			cache.insert(Artwork::new(
+				"image/png".into(),
+				i.to_le_bytes().to_vec().into(),
+			));
+			// keep the first artwork in use
+			assert!(cache.get(&first.hash).is_some());
+		}
+
+		let last = Artwork::new("image/png".into(), Bytes::from_static(&[1, 2, 3]));
+		cache.insert(last.clone());
+		assert!(cache.get(&first.hash).is_some());
+		assert!(cache.get(&last.hash).is_some());
+		assert_eq!(cache.memory.lock().unwrap().len(), CAPACITY.get());
+	}
+
+	#[test]
+	fn reads_artwork_back_from_disk() {
+		let dir = TempDir::default();
+		let artwork = png(1);
+		let processed = png(2);
+		let settings = ProcessingSettings::default();
+
+		let cache = ArtworkCache::new(Some(dir.to_path_buf()));
+		cache.insert(artwork.clone());
+		cache.insert_processed(&artwork.hash, &settings, processed.clone());
+
+		// a fresh cache has nothing in memory, so this must come from disk
+		let cache = ArtworkCache::new(Some(dir.to_path_buf()));
+		let cached = cache.get(&artwork.hash).unwrap();
+		assert_eq!(cached.bytes, artwork.bytes);
+		assert_eq!(cached.mime, "image/png");
+		let cached = cache.processed(&artwork.hash, &settings).unwrap();
+		assert_eq!(cached.hash, processed.hash);
+
+		let other_settings = ProcessingSettings {
+			max_dimension: 256,
+			..settings
+		};
+		assert!(cache.processed(&artwork.hash, &other_settings).is_none());
+		assert!(cache.get("../../etc/passwd").is_none());
+	}
+
+	#[test]
+	fn evicts_least_recently_used_from_disk() {
+		let dir = TempDir::default();
+		let mut cache = ArtworkCache::new(Some(dir.to_path_buf()));
+		let (first, second, third) = (png(1), png(2), png(3));
+		cache.disk.as_mut().unwrap().max_bytes = 2 * first.bytes.len() as u64;
+
+		cache.insert(first.clone());
+		// file times aren't necessarily precise enough to tell apart writes in quick succession
+		sleep(Duration::from_millis(50));
+		cache.insert(second.clone());
+		sleep(Duration::from_millis(50));
+		// using the first artwork makes the second the least recently used
+		cache.disk.as_ref().unwrap().get(&first.hash).unwrap();
+		sleep(Duration::from_millis(50));
+		cache.insert(third.clone());
+
+		assert!(dir.join(&first.hash).exists());
+		assert!(!dir.join(&second.hash).exists());
+		assert!(dir.join(&third.hash).exists());
+
+		cache.disk.as_mut().unwrap().max_age = Duration::ZERO;
+		cache.evict();
+		assert_eq!(fs::read_dir(&*dir).unwrap().count(), 0);
+	}
+}
@@ -0,0 +1,271 @@
+//! Removes metadata (EXIF, XMP, ICC profiles, comments, ...) from images without re-encoding
+//! them, by dropping the chunks or segments that carry it.
+
+use std::borrow::Cow;
+
+use anyhow::{Context, bail, ensure};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 6] = [b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"iCCP", b"tIME"];
+
+const WEBP_METADATA_CHUNKS: [&[u8; 4]; 3] = [b"EXIF", b"XMP ", b"ICCP"];
+/// The ICC, EXIF and XMP flags in the VP8X header.
+const VP8X_METADATA_FLAGS: u8 = 0x20 | 0x08 | 0x04;
+
+/// Strips metadata from JPEG, PNG and WebP images, borrowing the input when there's nothing to
+/// strip or the format isn't one we know how to sanitize.
+pub fn strip_metadata(bytes: &[u8]) -> anyhow::Result<Cow<'_, [u8]>> {
+	let stripped = if bytes.starts_with(&[0xff, 0xd8]) {
+		strip_jpeg(bytes).context("malformed JPEG")?
+	} else if bytes.starts_with(PNG_SIGNATURE) {
+		strip_png(bytes).context("malformed PNG")?
+	} else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+		strip_webp(bytes).context("malformed WebP")?
+	} else {
+		return Ok(Cow::Borrowed(bytes));
+	};
+
+	Ok(match stripped.len() == bytes.len() {
+		true => Cow::Borrowed(bytes),
+		false => Cow::Owned(stripped),
+	})
+}
+
+/// Keeps the JFIF (APP0) and Adobe (APP14) segments, which affect decoding, and drops all other
+/// application segments and comments.
+fn strip_jpeg(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+	let mut out = Vec::with_capacity(bytes.len());
+	out.extend_from_slice(&bytes[..2]);
+
+	let mut pos = 2;
+	loop {
+		ensure!(pos + 2 <= bytes.len(), "unexpected end of data");
+		ensure!(bytes[pos] == 0xff, "expected a marker at {pos}");
+
+		let marker = bytes[pos + 1];
+		match marker {
+			// fill bytes before a marker
+			0xff => {
+				pos += 1;
+				continue;
+			}
+			// markers without a length
+			0x01 | 0xd0..=0xd7 => {
+				out.extend_from_slice(&bytes[pos..pos + 2]);
+				pos += 2;
+				continue;
+			}
+			0xd9 => {
+				out.extend_from_slice(&bytes[pos..]);
+				return Ok(out);
+			}
+			_ => {}
+		}
+
+		ensure!(pos + 4 <= bytes.len(), "unexpected end of data");
+		let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+		let end = pos + 2 + len;
+		ensure!(len >= 2 && end <= bytes.len(), "segment overruns data");
+
+		let is_metadata = matches!(marker, 0xe1..=0xed | 0xef | 0xfe);
+		if !is_metadata {
+			out.extend_from_slice(&bytes[pos..end]);
+		}
+
+		// start of scan: the rest is entropy-coded data, which may contain anything
+		if marker == 0xda {
+			out.extend_from_slice(&bytes[end..]);
+			return Ok(out);
+		}
+
+		pos = end;
+	}
+}
+
+fn strip_png(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+	let mut out = Vec::with_capacity(bytes.len());
+	out.extend_from_slice(PNG_SIGNATURE);
+
+	let mut pos = PNG_SIGNATURE.len();
+	while pos < bytes.len() {
+		ensure!(pos + 8 <= bytes.len(), "unexpected end of data");
+		let len = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+		let kind = &bytes[pos + 4..pos + 8];
+		// length, type, data and CRC
+		let end = pos + 12 + len;
+		ensure!(end <= bytes.len(), "chunk overruns data");
+
+		if !PNG_METADATA_CHUNKS.iter().any(|chunk| chunk[..] == *kind) {
+			out.extend_from_slice(&bytes[pos..end]);
+		}
+
+		if kind == b"IEND" {
+			return Ok(out);
+		}
+		pos = end;
+	}
+
+	bail!("missing IEND chunk")
+}
+
+fn strip_webp(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+	let mut out = Vec::with_capacity(bytes.len());
+	out.extend_from_slice(&bytes[..12]);
+
+	let riff_end =
+		(u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize + 8).min(bytes.len());
+	let mut pos = 12;
+	while pos < riff_end {
+		ensure!(pos + 8 <= riff_end, "unexpected end of data");
+		let kind = &bytes[pos..pos + 4];
+		let len = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+		// chunks are padded to an even length
+		let end = (pos + 8 + len + (len & 1)).min(riff_end);
+		ensure!(pos + 8 + len <= riff_end, "chunk overruns data");
+
+		if kind == b"VP8X" {
+			ensure!(len >= 1, "empty VP8X chunk");
+			let flags = out.len() + 8;
+			out.extend_from_slice(&bytes[pos..end]);
+			out[flags] &= !VP8X_METADATA_FLAGS;
+		} else if !WEBP_METADATA_CHUNKS.iter().any(|chunk| chunk[..] == *kind) {
+			out.extend_from_slice(&bytes[pos..end]);
+		}
+
+		pos = end;
+	}
+
+	let riff_len = u32::try_from(out.len() - 8)?;
+	out[4..8].copy_from_slice(&riff_len.to_le_bytes());
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{borrow::Cow, io::Cursor};
+
+	use image::{DynamicImage, ImageFormat, RgbImage};
+
+	use super::strip_metadata;
+
+	fn encode(format: ImageFormat) -> Vec<u8> {
+		let image = DynamicImage::from(RgbImage::from_fn(16, 16, |x, y| {
+			image::Rgb([x as u8 * 16, y as u8 * 16, 128])
+		}));
+		let mut bytes = Cursor::new(Vec::new());
+		image.write_to(&mut bytes, format).unwrap();
+		bytes.into_inner()
+	}
+
+	fn crc32(data: &[u8]) -> u32 {
+		let mut crc = !0u32;
+		for byte in data {
+			crc ^= *byte as u32;
+			for _ in 0..8 {
+				crc = (crc >> 1) ^ (0xedb8_8320 & (!(crc & 1)).wrapping_add(1));
+			}
+		}
+		!crc
+	}
+
+	fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+		let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+		chunk.extend_from_slice(kind);
+		chunk.extend_from_slice(data);
+		let crc = crc32(&chunk[4..]);
+		chunk.extend_from_slice(&crc.to_be_bytes());
+		chunk
+	}
+
+	fn webp_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+		let mut chunk = kind.to_vec();
+		chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		chunk.extend_from_slice(data);
+		if data.len() % 2 == 1 {
+			chunk.push(0);
+		}
+		chunk
+	}
+
+	#[test]
+	fn strips_jpeg_exif_and_comments() {
+		let original = encode(ImageFormat::Jpeg);
+		assert!(matches!(
+			strip_metadata(&original).unwrap(),
+			Cow::Borrowed(_)
+		));
+
+		let exif = b"Exif\0\0GPS goes here";
+		let mut tagged = original[..2].to_vec();
+		tagged.extend_from_slice(&[0xff, 0xe1]);
+		tagged.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+		tagged.extend_from_slice(exif);
+		tagged.extend_from_slice(&[0xff, 0xfe, 0x00, 0x07]);
+		tagged.extend_from_slice(b"hello");
+		tagged.extend_from_slice(&original[2..]);
+
+		assert_eq!(strip_metadata(&tagged).unwrap(), original);
+	}
+
+	#[test]
+	fn strips_png_text_and_icc_chunks() {
+		let original = encode(ImageFormat::Png);
+		assert!(matches!(
+			strip_metadata(&original).unwrap(),
+			Cow::Borrowed(_)
+		));
+
+		// metadata goes after IHDR, which is 25 bytes following the signature
+		let mut tagged = original[..33].to_vec();
+		tagged.extend(png_chunk(b"tEXt", b"Software\0some encoder"));
+		tagged.extend(png_chunk(b"iCCP", b"profile\0\0data"));
+		tagged.extend_from_slice(&original[33..]);
+		image::load_from_memory(&tagged).unwrap();
+
+		assert_eq!(strip_metadata(&tagged).unwrap(), original);
+	}
+
+	#[test]
+	fn strips_webp_metadata_and_flags() {
+		let lossless = encode(ImageFormat::WebP);
+		let vp8l = &lossless[12..];
+
+		let mut vp8x = vec![0x20 | 0x08 | 0x04, 0, 0, 0];
+		// canvas width and height minus one, as 24-bit little-endian
+		vp8x.extend_from_slice(&[15, 0, 0, 15, 0, 0]);
+
+		let mut tagged = b"RIFF\0\0\0\0WEBP".to_vec();
+		tagged.extend(webp_chunk(b"VP8X", &vp8x));
+		tagged.extend(webp_chunk(b"ICCP", b"profile"));
+		tagged.extend_from_slice(vp8l);
+		tagged.extend(webp_chunk(b"EXIF", b"Exif\0\0GPS"));
+		tagged.extend(webp_chunk(b"XMP ", b"<x:xmpmeta/>"));
+		let len = (tagged.len() as u32 - 8).to_le_bytes();
+		tagged[4..8].copy_from_slice(&len);
+
+		let stripped = strip_metadata(&tagged).unwrap();
+
+		let mut expected = b"RIFF\0\0\0\0WEBP".to_vec();
+		vp8x[0] = 0;
+		expected.extend(webp_chunk(b"VP8X", &vp8x));
+		expected.extend_from_slice(vp8l);
+		let len = (expected.len() as u32 - 8).to_le_bytes();
+		expected[4..8].copy_from_slice(&len);
+		assert_eq!(stripped, expected);
+		image::load_from_memory(&stripped).unwrap();
+	}
+
+	#[test]
+	fn rejects_truncated_images() {
+		let original = encode(ImageFormat::Png);
+		assert!(strip_metadata(&original[..40]).is_err());
+	}
+
+	#[test]
+	fn leaves_unknown_formats_alone() {
+		assert!(matches!(
+			strip_metadata(b"GIF89a...").unwrap(),
+			Cow::Borrowed(_)
+		));
+	}
+}
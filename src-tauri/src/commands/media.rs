@@ -4,10 +4,12 @@ use tracing::Level;
 use crate::{
 	error::AppResult,
 	media::{self, Media},
+	pipeline,
 };
 
 #[tauri::command]
 #[tracing::instrument(skip_all, ret, err, level = Level::INFO)]
 pub async fn get_media(app: AppHandle) -> AppResult<Option<Media>> {
-	media::get(app).await
+	let media = media::get(app.clone()).await?;
+	pipeline::prepare(&app, media).await
 }
@@ -0,0 +1,37 @@
+use tauri::{AppHandle, State};
+use tracing::Level;
+
+use crate::{
+	error::AppResult,
+	settings::Settings,
+	state::{ApiState, PlayerFilterState, ServerState, SettingsState},
+};
+
+#[tauri::command]
+#[tracing::instrument(skip_all, level = Level::INFO)]
+pub async fn get_settings(settings: State<'_, SettingsState>) -> AppResult<Settings> {
+	Ok(settings.read().await.clone())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, ret, err, level = Level::INFO)]
+pub async fn set_settings(
+	app: AppHandle,
+	settings: Settings,
+	state: State<'_, SettingsState>,
+	api: State<'_, ApiState>,
+	server: State<'_, ServerState>,
+	filter: State<'_, PlayerFilterState>,
+) -> AppResult<()> {
+	let mut server = server.lock().await;
+	let new_api = settings.api(server.store())?;
+	let new_filter = settings.players.filter()?;
+	server.apply(&settings.server).await?;
+
+	settings.save(&app)?;
+	*api.write().await = new_api;
+	*filter.write().await = new_filter;
+	*state.write().await = settings;
+
+	Ok(())
+}
@@ -1,29 +1,45 @@
-use std::env;
-
 use error::AppResult;
-use futures::TryStreamExt;
 use tauri::{
 	Emitter, Manager,
-	async_runtime::spawn,
+	async_runtime::{block_on, spawn, spawn_blocking},
 	menu::{Menu, MenuItem},
 	tray::{MouseButton, TrayIconBuilder, TrayIconEvent},
 };
 use tauri_plugin_autostart::MacosLauncher;
+use tokio::sync::broadcast::error::RecvError;
 use tracing::Level;
 
-use crate::{api::Api, state::RpcState};
+use crate::{
+	api::UploadLedger,
+	artwork::{ArtworkCache, ArtworkProcessor},
+	media::{Backoff, MediaError, MediaHub},
+	server::{ArtworkServer, ArtworkStore},
+	settings::Settings,
+	state::{ApiState, PlayerFilterState, PresenceState, RpcState, ServerState, SettingsState},
+};
 
 use commands::{
 	media::get_media,
-	rpc::{connect, set_activity},
+	rpc::connect,
+	settings::{get_settings, set_settings},
 };
 
 mod api;
+mod artwork;
 mod commands;
 mod error;
 mod media;
+mod pipeline;
+mod presence;
+#[cfg(any(target_os = "macos", test))]
+mod process;
+mod protocol;
 mod rpc;
+mod server;
+mod settings;
 mod state;
+#[cfg(test)]
+mod testing;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() -> AppResult<()> {
@@ -36,14 +52,89 @@ pub fn run() -> AppResult<()> {
 
 	tauri::Builder::default()
 		.plugin(tauri_plugin_store::Builder::new().build())
+		.register_asynchronous_uri_scheme_protocol(protocol::ARTWORK_SCHEME, protocol::artwork)
 		.setup(|app| {
+			let settings = Settings::load(app.handle()).unwrap_or_else(|err| {
+				tracing::warn!(%err, "failed to load settings, using defaults");
+				Settings::default()
+			});
+			let data_dir = app.path().app_data_dir()?;
+			app.manage(UploadLedger::load(data_dir.join("uploads.json")));
+
+			app.manage(ArtworkCache::new(Some(data_dir.join("artwork-cache"))));
+			let handle = app.handle().clone();
+			spawn_blocking(move || handle.state::<ArtworkCache>().evict());
+
+			let store = ArtworkStore::new(Some(data_dir.join("served-artwork")));
+			let mut server = ArtworkServer::new(store.clone());
+			if let Err(err) = block_on(server.apply(&settings.server)) {
+				tracing::warn!(%err, "failed to start artwork server");
+			}
+			let api = settings.api(&store).unwrap_or_else(|err| {
+				tracing::warn!(%err, "invalid artwork settings, disabling uploads");
+				None
+			});
+			let filter = settings.players.filter().unwrap_or_else(|err| {
+				tracing::warn!(%err, "invalid player rules, allowing all players");
+				Default::default()
+			});
+			app.manage(ApiState::new(api));
+			app.manage(PlayerFilterState::new(filter));
+			app.manage(ServerState::new(server));
+			app.manage(SettingsState::new(settings));
+
 			let handle = app.handle().clone();
 			spawn(async move {
-				let mut subscription = media::subscribe(handle.clone()).await.unwrap();
+				media::supervise(
+					Backoff::default(),
+					async || Ok(media::subscribe(handle.clone()).await?),
+					async |properties| match pipeline::prepare(&handle, properties).await {
+						Ok(properties) => {
+							tracing::info!(?properties, "media change");
+							handle.state::<MediaHub>().publish(properties);
+						}
+						// the subscription is fine, so only this update is lost
+						Err(err) => {
+							let _ = handle.emit("media_error", MediaError::new(&err.0, None));
+						}
+					},
+					|err| {
+						let _ = handle.emit("media_error", err);
+					},
+				)
+				.await
+			});
 
-				while let Some(properties) = subscription.try_next().await.unwrap() {
-					tracing::info!(?properties, "media change");
-					handle.emit("media_change", properties).unwrap();
+			let handle = app.handle().clone();
+			let mut media = handle.state::<MediaHub>().watch();
+			spawn(async move {
+				while media.changed().await.is_ok() {
+					let media = media.borrow_and_update().clone();
+					handle.emit("media_change", media).unwrap();
+				}
+			});
+
+			let handle = app.handle().clone();
+			let mut media = handle.state::<MediaHub>().watch();
+			spawn(async move {
+				while media.changed().await.is_ok() {
+					let media = media.borrow_and_update().clone();
+					// already logged, and the next update tries again
+					let _ = presence::set(&handle, media).await;
+				}
+			});
+
+			let handle = app.handle().clone();
+			let mut events = handle.state::<MediaHub>().events();
+			spawn(async move {
+				loop {
+					match events.recv().await {
+						Ok(event) => handle.emit("media_event", event).unwrap(),
+						Err(RecvError::Lagged(missed)) => {
+							tracing::warn!(missed, "webview fell behind on media events")
+						}
+						Err(RecvError::Closed) => break,
+					}
 				}
 			});
 
@@ -92,9 +183,16 @@ pub fn run() -> AppResult<()> {
 			MacosLauncher::LaunchAgent,
 			None,
 		))
-		.manage(Api::new(env!("API_URL")))
 		.manage(RpcState::new(None))
-		.invoke_handler(tauri::generate_handler![get_media, set_activity, connect,])
+		.manage(PresenceState::default())
+		.manage(ArtworkProcessor::default())
+		.manage(MediaHub::default())
+		.invoke_handler(tauri::generate_handler![
+			get_media,
+			connect,
+			get_settings,
+			set_settings,
+		])
 		.run(tauri::generate_context!())
 		.expect("error while running tauri application");
 
@@ -1,77 +1,234 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, path::PathBuf};
 
-use jiff::Timestamp;
+use bytes::Bytes;
+use jiff::{SignedDuration, Timestamp};
 use serde::{Deserialize, Serialize};
 
+#[cfg(any(target_os = "macos", test))]
+mod adapter;
+mod events;
+mod filter;
+mod hub;
 #[cfg(target_os = "macos")]
 mod mac;
+mod player;
+mod supervisor;
 #[cfg(windows)]
 mod win;
 
+pub use events::{MediaEvent, MediaTracker};
+pub use filter::{PlayerFilter, PlayerSettings};
+pub use hub::MediaHub;
 #[cfg(target_os = "macos")]
 pub use mac::*;
+pub use player::Player;
+pub use supervisor::{Backoff, MediaError, supervise};
 #[cfg(windows)]
 pub use win::*;
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Media {
 	pub title: String,
 	pub artist: String,
+	/// The app that's playing, if the platform reports it.
+	#[serde(default)]
+	pub player: Option<Player>,
+	#[serde(default)]
+	pub album: Option<String>,
+	#[serde(default)]
+	pub album_artist: Option<String>,
+	#[serde(default)]
+	pub track_number: Option<u32>,
+	#[serde(default)]
+	pub genres: Vec<String>,
+	pub state: PlaybackState,
+	/// How fast the track plays, where 1 is normal speed.
+	#[serde(default = "normal_rate")]
+	pub rate: f64,
+	/// How far into the track playback was when the media was reported.
+	pub position: SignedDuration,
+	/// When the track started and ends, assuming playback continues uninterrupted at `rate`, so
+	/// these are only meaningful while playing.
 	pub start: Timestamp,
-	pub end: Timestamp,
-	pub artwork_mime: String,
-	#[serde(with = "artwork_bytes")]
-	pub artwork_bytes: Vec<u8>,
-	pub artwork_hash: String,
+	/// `None` for live streams, which don't have a known duration.
+	pub end: Option<Timestamp>,
+	/// Not every track has artwork, and not every player reports it.
+	pub artwork: Option<ArtworkSource>,
+}
+
+impl Media {
+	/// Whether both are the same track from the same player, regardless of playback.
+	pub fn same_track(&self, other: &Media) -> bool {
+		self.title == other.title
+			&& self.artist == other.artist
+			&& self.album == other.album
+			&& self.player.as_ref().map(|player| &player.id)
+				== other.player.as_ref().map(|player| &player.id)
+	}
+}
+
+fn normal_rate() -> f64 {
+	1.0
+}
+
+/// Players report a rate of 0 while paused, which says nothing about how fast playback resumes.
+pub fn playback_rate(rate: Option<f64>) -> f64 {
+	rate.filter(|rate| rate.is_finite() && *rate > 0.0)
+		.unwrap_or_else(normal_rate)
+}
+
+/// When a track started and ends, given how far into it playback was at `reported_at`. Playing at
+/// 2× makes a three minute track end a minute and a half after it started. Live streams report
+/// no duration or a duration of 0, so they don't end.
+pub fn timeline(
+	reported_at: Timestamp,
+	position: SignedDuration,
+	duration: Option<SignedDuration>,
+	rate: f64,
+) -> (Timestamp, Option<Timestamp>) {
+	let start = reported_at - position.div_f64(rate);
+	let end = duration
+		.filter(|duration| *duration > SignedDuration::ZERO)
+		.map(|duration| start + duration.div_f64(rate));
+	(start, end)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackState {
+	Playing,
+	Paused,
+	Stopped,
+}
+
+/// Where a player's artwork comes from. Only raw bytes need uploading, since `https` URLs can be
+/// shown as-is and local files are read into bytes when media is prepared.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ArtworkSource {
+	Bytes(Artwork),
+	File {
+		path: PathBuf,
+	},
+	/// Any URL a player reports, such as MPRIS's `file://` URLs, until media is prepared.
+	Url {
+		url: String,
+	},
+}
+
+/// Artwork bytes aren't serialized, since the webview loads artwork from the artwork cache via the
+/// `artwork` URI scheme by its hash instead.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Artwork {
+	pub mime: String,
+	#[serde(skip)]
+	pub bytes: Bytes,
+	pub hash: String,
+}
+
+impl Artwork {
+	pub fn new(mime: String, bytes: Bytes) -> Self {
+		Self {
+			mime,
+			hash: blake3::hash(&bytes).to_hex().to_string(),
+			bytes,
+		}
+	}
 }
 
-impl Debug for Media {
+#[cfg(test)]
+impl Media {
+	/// A three minute track that started at the beginning of 2025.
+	pub(crate) fn fixture(title: &str, state: PlaybackState) -> Self {
+		use jiff::ToSpan;
+
+		let start: Timestamp = "2025-01-01T00:00:00Z".parse().unwrap();
+		Self {
+			title: title.into(),
+			artist: "Artist".into(),
+			player: None,
+			album: None,
+			album_artist: None,
+			track_number: None,
+			genres: vec![],
+			state,
+			rate: 1.0,
+			position: SignedDuration::ZERO,
+			start,
+			end: Some(start + 3.minutes()),
+			artwork: None,
+		}
+	}
+}
+
+impl Debug for Artwork {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		f.debug_struct("Media")
-			.field("title", &self.title)
-			.field("artist", &self.artist)
-			.field("start", &self.start)
-			.field("end", &self.end)
-			.field("artwork_mime", &self.artwork_mime)
-			.field("artwork_bytes", &"<bytes>")
-			.field("artwork_hash", &self.artwork_hash)
+		f.debug_struct("Artwork")
+			.field("mime", &self.mime)
+			.field("bytes", &"<bytes>")
+			.field("hash", &self.hash)
 			.finish()
 	}
 }
 
-mod artwork_bytes {
-	use base64::{Engine, prelude::BASE64_STANDARD};
-	use serde::{Deserializer, Serializer, de::Visitor};
+#[cfg(test)]
+mod tests {
+	use jiff::{SignedDuration, Timestamp};
+
+	use super::{playback_rate, timeline};
 
-	pub fn serialize<S>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
-	where
-		S: Serializer,
-	{
-		let encoded = BASE64_STANDARD.encode(bytes);
-		serializer.serialize_str(&encoded)
+	fn timeline_at(position: i64, rate: f64) -> (Timestamp, Option<Timestamp>) {
+		timeline(
+			"2025-01-01T00:01:00Z".parse().unwrap(),
+			SignedDuration::from_secs(position),
+			Some(SignedDuration::from_mins(3)),
+			rate,
+		)
 	}
 
-	pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
-	where
-		D: Deserializer<'de>,
-	{
-		struct Base64Visitor;
-
-		impl<'de> Visitor<'de> for Base64Visitor {
-			type Value = Vec<u8>;
-
-			fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-				formatter.write_str("base64 string")
-			}
-
-			fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-			where
-				E: serde::de::Error,
-			{
-				BASE64_STANDARD.decode(v).map_err(|e| E::custom(e))
-			}
+	fn at(timestamp: &str) -> Option<Timestamp> {
+		Some(timestamp.parse().unwrap())
+	}
+
+	#[test]
+	fn plays_at_normal_speed() {
+		let (start, end) = timeline_at(60, 1.0);
+		assert_eq!(Some(start), at("2025-01-01T00:00:00Z"));
+		assert_eq!(end, at("2025-01-01T00:03:00Z"));
+	}
+
+	#[test]
+	fn scales_timestamps_by_rate() {
+		// a minute into the track took 30 seconds, and the rest takes another minute
+		let (start, end) = timeline_at(60, 2.0);
+		assert_eq!(Some(start), at("2025-01-01T00:00:30Z"));
+		assert_eq!(end, at("2025-01-01T00:02:00Z"));
+
+		let (start, end) = timeline_at(90, 1.5);
+		assert_eq!(Some(start), at("2025-01-01T00:00:00Z"));
+		assert_eq!(end, at("2025-01-01T00:02:00Z"));
+
+		let (start, end) = timeline_at(30, 0.5);
+		assert_eq!(Some(start), at("2025-01-01T00:00:00Z"));
+		assert_eq!(end, at("2025-01-01T00:06:00Z"));
+	}
+
+	#[test]
+	fn live_streams_dont_end() {
+		let reported_at = "2025-01-01T00:01:00Z".parse().unwrap();
+		for duration in [None, Some(SignedDuration::ZERO)] {
+			let (start, end) = timeline(reported_at, SignedDuration::from_secs(60), duration, 1.0);
+			assert_eq!(Some(start), at("2025-01-01T00:00:00Z"));
+			assert_eq!(end, None);
 		}
+	}
 
-		deserializer.deserialize_str(Base64Visitor)
+	#[test]
+	fn assumes_normal_speed_for_unusable_rates() {
+		assert_eq!(playback_rate(None), 1.0);
+		assert_eq!(playback_rate(Some(0.0)), 1.0);
+		assert_eq!(playback_rate(Some(-1.0)), 1.0);
+		assert_eq!(playback_rate(Some(f64::NAN)), 1.0);
+		assert_eq!(playback_rate(Some(1.25)), 1.25);
 	}
 }
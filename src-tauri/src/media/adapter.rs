@@ -0,0 +1,387 @@
+//! The JSON protocol spoken by mediaremote-adapter on macOS. It's kept apart from spawning the
+//! adapter so that it can be tested on any platform.
+
+use anyhow::Context;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use bytes::Bytes;
+use jiff::{SignedDuration, Timestamp};
+use serde::{Deserialize, Deserializer, de::Visitor};
+use serde_json::from_str;
+
+use crate::media::{Artwork, ArtworkSource, Media, PlaybackState, Player, playback_rate, timeline};
+
+/// What the adapter last reported, merged from its full and diff payloads.
+#[derive(Debug, Clone, Default)]
+pub struct NowPlayingInfo {
+	pub bundle_identifier: Option<String>,
+	/// Set when media is played by a helper process, like a browser's web content process.
+	pub parent_application_bundle_identifier: Option<String>,
+	pub playing: bool,
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
+	pub track_number: Option<u32>,
+	pub genre: Option<String>,
+	pub duration: Option<f32>,
+	pub elapsed_time: Option<f32>,
+	/// 0 while paused.
+	pub playback_rate: Option<f32>,
+	pub timestamp: Option<Timestamp>,
+	pub artwork_mime_type: Option<String>,
+	pub artwork_data: Option<Bytes>,
+	/// Built from the two fields above whenever either changes, so artwork is only hashed once
+	/// rather than on every elapsed time or playback update.
+	pub artwork: Option<Artwork>,
+	pub chapter_number: Option<usize>,
+}
+
+impl NowPlayingInfo {
+	/// Applies a line of the adapter's output, which either replaces everything or only changes
+	/// the fields it mentions.
+	pub fn apply(&mut self, payload: StreamPayload) {
+		if !payload.diff {
+			*self = Self::default();
+		}
+
+		let patch = payload.payload;
+		if let Some(playing) = patch.playing {
+			self.playing = playing.unwrap_or_default();
+		}
+		merge(&mut self.bundle_identifier, patch.bundle_identifier);
+		merge(
+			&mut self.parent_application_bundle_identifier,
+			patch.parent_application_bundle_identifier,
+		);
+		merge(&mut self.title, patch.title);
+		merge(&mut self.artist, patch.artist);
+		merge(&mut self.album, patch.album);
+		merge(&mut self.track_number, patch.track_number);
+		merge(&mut self.genre, patch.genre);
+		merge(&mut self.duration, patch.duration);
+		merge(&mut self.elapsed_time, patch.elapsed_time);
+		merge(&mut self.playback_rate, patch.playback_rate);
+		merge(&mut self.timestamp, patch.timestamp);
+		merge(&mut self.chapter_number, patch.chapter_number);
+
+		if patch.artwork_mime_type.is_some() || patch.artwork_data.is_some() {
+			merge(&mut self.artwork_mime_type, patch.artwork_mime_type);
+			merge(&mut self.artwork_data, patch.artwork_data);
+			self.artwork = match (&self.artwork_mime_type, &self.artwork_data) {
+				(Some(mime), Some(bytes)) => Some(Artwork::new(mime.clone(), bytes.clone())),
+				_ => None,
+			};
+		}
+	}
+}
+
+/// Changes to [`NowPlayingInfo`], where a missing field is unchanged and `null` clears it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NowPlayingPatch {
+	#[serde(deserialize_with = "patch")]
+	pub bundle_identifier: Option<Option<String>>,
+	#[serde(deserialize_with = "patch")]
+	pub parent_application_bundle_identifier: Option<Option<String>>,
+	#[serde(deserialize_with = "patch")]
+	pub playing: Option<Option<bool>>,
+	#[serde(deserialize_with = "patch")]
+	pub title: Option<Option<String>>,
+	#[serde(deserialize_with = "patch")]
+	pub artist: Option<Option<String>>,
+	#[serde(deserialize_with = "patch")]
+	pub album: Option<Option<String>>,
+	#[serde(deserialize_with = "patch")]
+	pub track_number: Option<Option<u32>>,
+	#[serde(deserialize_with = "patch")]
+	pub genre: Option<Option<String>>,
+	#[serde(deserialize_with = "patch")]
+	pub duration: Option<Option<f32>>,
+	#[serde(deserialize_with = "patch")]
+	pub elapsed_time: Option<Option<f32>>,
+	#[serde(deserialize_with = "patch")]
+	pub playback_rate: Option<Option<f32>>,
+	#[serde(deserialize_with = "patch")]
+	pub timestamp: Option<Option<Timestamp>>,
+	#[serde(deserialize_with = "patch")]
+	pub artwork_mime_type: Option<Option<String>>,
+	#[serde(deserialize_with = "patch_artwork_data")]
+	pub artwork_data: Option<Option<Bytes>>,
+	#[serde(deserialize_with = "patch")]
+	pub chapter_number: Option<Option<usize>>,
+}
+
+fn merge<T>(field: &mut Option<T>, patch: Option<Option<T>>) {
+	if let Some(value) = patch {
+		*field = value;
+	}
+}
+
+/// Only called for fields that are present, so that `null` becomes `Some(None)`.
+fn patch<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: Deserialize<'de>,
+{
+	Option::deserialize(deserializer).map(Some)
+}
+
+fn patch_artwork_data<'de, D>(deserializer: D) -> Result<Option<Option<Bytes>>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	deserialize_artwork_data(deserializer).map(Some)
+}
+
+impl From<NowPlayingInfo> for Option<Media> {
+	fn from(value: NowPlayingInfo) -> Self {
+		// the adapter can't tell paused and stopped apart
+		let state = match value.playing {
+			true => PlaybackState::Playing,
+			false => PlaybackState::Paused,
+		};
+
+		let rate = playback_rate(value.playback_rate.map(f64::from));
+		let elapsed_duration = SignedDuration::from_secs_f32(value.elapsed_time?);
+		// live streams don't have a duration
+		let playback_duration = value.duration.map(SignedDuration::from_secs_f32);
+		let (start, end) = timeline(value.timestamp?, elapsed_duration, playback_duration, rate);
+
+		let player = value
+			.parent_application_bundle_identifier
+			.or(value.bundle_identifier)
+			.and_then(|id| Player::from_id(&id));
+
+		Some(Media {
+			artist: value.artist?,
+			player,
+			album: value.album,
+			album_artist: None,
+			track_number: value.track_number,
+			genres: value.genre.into_iter().collect(),
+			state,
+			rate,
+			position: elapsed_duration,
+			start,
+			end,
+			title: value.title?,
+			artwork: value.artwork.map(ArtworkSource::Bytes),
+		})
+	}
+}
+
+fn deserialize_artwork_data<'de, D>(deserializer: D) -> Result<Option<Bytes>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	pub struct Base64Visitor;
+
+	impl<'de> Visitor<'de> for Base64Visitor {
+		type Value = Option<Bytes>;
+
+		fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+			formatter.write_str("base64 string")
+		}
+
+		fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+		where
+			E: serde::de::Error,
+		{
+			BASE64_STANDARD
+				.decode(v)
+				.map(|bytes| Some(bytes.into()))
+				.map_err(|err| E::custom(err))
+		}
+
+		fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+		where
+			D: Deserializer<'de>,
+		{
+			deserializer.deserialize_str(self)
+		}
+
+		fn visit_none<E>(self) -> Result<Self::Value, E>
+		where
+			E: serde::de::Error,
+		{
+			Ok(None)
+		}
+	}
+
+	deserializer.deserialize_option(Base64Visitor)
+}
+
+/// A line of the adapter's `stream` output.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamPayload {
+	/// Whether `payload` only holds what changed since the last line.
+	pub diff: bool,
+	pub payload: NowPlayingPatch,
+}
+
+impl StreamPayload {
+	pub fn parse(line: &str) -> anyhow::Result<Self> {
+		from_str(line).context("malformed mediaremote-adapter output")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use jiff::SignedDuration;
+
+	use super::{NowPlayingInfo, StreamPayload};
+	use crate::media::{ArtworkSource, Media, PlaybackState};
+
+	fn info(lines: &[&str]) -> NowPlayingInfo {
+		let mut info = NowPlayingInfo::default();
+		for line in lines {
+			info.apply(StreamPayload::parse(line).unwrap());
+		}
+		info
+	}
+
+	fn media(line: &str) -> Option<Media> {
+		info(&[line]).into()
+	}
+
+	#[test]
+	fn converts_playing_media() {
+		let media = media(include_str!("adapter/fixtures/playing.json")).unwrap();
+
+		assert_eq!(media.title, "Song");
+		assert_eq!(media.artist, "Artist");
+		assert_eq!(media.album.as_deref(), Some("Album"));
+		assert_eq!(media.track_number, Some(3));
+		assert_eq!(media.genres, ["Pop"]);
+		assert_eq!(media.state, PlaybackState::Playing);
+		// helper processes are attributed to the app they belong to
+		assert_eq!(media.player.unwrap().id, "com.apple.Safari");
+		assert_eq!(media.position, SignedDuration::from_secs(30));
+		assert_eq!(media.start, "2025-01-01T00:00:00Z".parse().unwrap());
+		assert_eq!(media.end, Some("2025-01-01T00:03:00.5Z".parse().unwrap()));
+
+		let Some(ArtworkSource::Bytes(artwork)) = media.artwork else {
+			panic!("expected artwork bytes");
+		};
+		assert_eq!(artwork.mime, "image/png");
+		assert_eq!(&artwork.bytes[..], b"\x89PNG\r\n\x1a\n");
+	}
+
+	#[test]
+	fn converts_paused_media_without_artwork() {
+		let media = media(include_str!("adapter/fixtures/paused_null_artwork.json")).unwrap();
+
+		assert_eq!(media.state, PlaybackState::Paused);
+		assert_eq!(media.rate, 1.0);
+		assert_eq!(media.player.unwrap().name, "Spotify");
+		assert!(media.artwork.is_none());
+		assert!(media.album.is_none());
+		assert!(media.genres.is_empty());
+	}
+
+	#[test]
+	fn converts_live_streams() {
+		let media = media(include_str!("adapter/fixtures/live.json")).unwrap();
+		assert_eq!(media.start, "2025-01-01T00:00:00Z".parse().unwrap());
+		assert_eq!(media.end, None);
+
+		let media = Option::<Media>::from(info(&[
+			include_str!("adapter/fixtures/live.json"),
+			r#"{"diff":true,"payload":{"duration":0}}"#,
+		]))
+		.unwrap();
+		assert_eq!(media.end, None);
+	}
+
+	#[test]
+	fn ignores_incomplete_media() {
+		assert!(media(include_str!("adapter/fixtures/missing_artist.json")).is_none());
+		assert!(media(include_str!("adapter/fixtures/nothing_playing.json")).is_none());
+	}
+
+	#[test]
+	fn merges_diff_payloads() {
+		let info = info(&[
+			include_str!("adapter/fixtures/playing.json"),
+			include_str!("adapter/fixtures/diff.json"),
+		]);
+
+		assert!(!info.playing);
+		assert_eq!(info.elapsed_time, Some(61.5));
+		// fields missing from the diff are unchanged, and null ones are cleared
+		assert_eq!(info.title.as_deref(), Some("Song"));
+		assert_eq!(info.artwork_mime_type.as_deref(), Some("image/png"));
+		assert!(info.artwork_data.is_none());
+		assert!(info.artwork.is_none());
+	}
+
+	#[test]
+	fn full_payloads_replace_everything() {
+		let info = info(&[
+			include_str!("adapter/fixtures/playing.json"),
+			include_str!("adapter/fixtures/nothing_playing.json"),
+		]);
+
+		assert!(info.title.is_none());
+		assert!(info.artwork_data.is_none());
+		assert!(Option::<Media>::from(info).is_none());
+	}
+
+	#[test]
+	fn keeps_artwork_across_diffs() {
+		let info = info(&[
+			include_str!("adapter/fixtures/playing.json"),
+			r#"{"diff":true,"payload":{"elapsedTime":45}}"#,
+			r#"{"diff":true,"payload":{"playing":null}}"#,
+		]);
+
+		assert!(!info.playing);
+		assert_eq!(info.elapsed_time, Some(45.0));
+		let media = Option::<Media>::from(info).unwrap();
+		assert!(matches!(media.artwork, Some(ArtworkSource::Bytes(_))));
+	}
+
+	#[test]
+	fn rebuilds_artwork_when_it_changes() {
+		let playing = info(&[include_str!("adapter/fixtures/playing.json")]);
+		let first = playing.artwork.clone().unwrap();
+
+		let info = info(&[
+			include_str!("adapter/fixtures/playing.json"),
+			r#"{"diff":true,"payload":{"artworkData":"/9j/4AAQ"}}"#,
+			r#"{"diff":true,"payload":{"artworkMimeType":"image/jpeg"}}"#,
+		]);
+		let second = info.artwork.unwrap();
+		assert_ne!(second.hash, first.hash);
+		assert_eq!(second.mime, "image/jpeg");
+		assert_eq!(&second.bytes[..], [0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10]);
+	}
+
+	#[test]
+	fn scales_timestamps_by_playback_rate() {
+		let media = Option::<Media>::from(info(&[
+			include_str!("adapter/fixtures/playing.json"),
+			r#"{"diff":true,"payload":{"playbackRate":2}}"#,
+		]))
+		.unwrap();
+
+		assert_eq!(media.rate, 2.0);
+		assert_eq!(media.start, "2025-01-01T00:00:15Z".parse().unwrap());
+		assert_eq!(media.end, Some("2025-01-01T00:01:45.25Z".parse().unwrap()));
+	}
+
+	#[test]
+	fn rejects_malformed_lines() {
+		for line in [
+			"",
+			"not json",
+			r#"{"diff":false}"#,
+			r#"{"diff":false,"payload":{"title":3}}"#,
+			r#"{"diff":false,"payload":{"artworkData":"not base64!"}}"#,
+			r#"{"diff":false,"payload":{"timestamp":"yesterday"}}"#,
+		] {
+			let err = StreamPayload::parse(line).unwrap_err();
+			assert_eq!(err.to_string(), "malformed mediaremote-adapter output");
+		}
+	}
+}
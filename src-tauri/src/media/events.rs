@@ -0,0 +1,233 @@
+use jiff::SignedDuration;
+use serde::Serialize;
+
+use super::{ArtworkSource, Media, PlaybackState};
+
+/// Backends report positions slightly differently on every update, so smaller jumps aren't seeks.
+const SEEK_TOLERANCE: SignedDuration = SignedDuration::from_secs(2);
+
+/// What changed between two media snapshots.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MediaEvent {
+	/// A different track started, which covers any other changes to it.
+	TrackChanged {
+		previous: Option<Media>,
+		current: Media,
+	},
+	Seeked {
+		previous: Media,
+		current: Media,
+	},
+	Paused {
+		previous: Media,
+		current: Media,
+	},
+	Resumed {
+		previous: Media,
+		current: Media,
+	},
+	/// Playback stopped, or the player went away entirely.
+	Stopped {
+		previous: Media,
+		current: Option<Media>,
+	},
+	ArtworkChanged {
+		previous: Media,
+		current: Media,
+	},
+}
+
+/// Turns media snapshots into events by remembering the last one.
+#[derive(Debug, Default)]
+pub struct MediaTracker {
+	current: Option<Media>,
+}
+
+impl MediaTracker {
+	pub fn update(&mut self, media: Option<Media>) -> Vec<MediaEvent> {
+		let previous = std::mem::replace(&mut self.current, media.clone());
+		diff(previous, media)
+	}
+}
+
+pub fn diff(previous: Option<Media>, current: Option<Media>) -> Vec<MediaEvent> {
+	let current = match current {
+		Some(current) if current.state != PlaybackState::Stopped => current,
+		current => {
+			return match previous {
+				Some(previous) if previous.state != PlaybackState::Stopped => {
+					vec![MediaEvent::Stopped { previous, current }]
+				}
+				_ => vec![],
+			};
+		}
+	};
+	let previous = match previous {
+		Some(previous) if previous.same_track(&current) => previous,
+		previous => return vec![MediaEvent::TrackChanged { previous, current }],
+	};
+
+	let mut events = Vec::new();
+	let event = |kind: fn(Media, Media) -> MediaEvent| kind(previous.clone(), current.clone());
+
+	match (previous.state, current.state) {
+		(PlaybackState::Playing, PlaybackState::Paused) => {
+			events.push(event(|previous, current| MediaEvent::Paused {
+				previous,
+				current,
+			}));
+		}
+		(PlaybackState::Paused | PlaybackState::Stopped, PlaybackState::Playing) => {
+			events.push(event(|previous, current| MediaEvent::Resumed {
+				previous,
+				current,
+			}));
+		}
+		_ => {}
+	}
+
+	// positions can only be compared reliably when the state hasn't changed
+	let seeked = match (previous.state, current.state) {
+		(PlaybackState::Playing, PlaybackState::Playing) => {
+			current.start.duration_since(previous.start).abs() > SEEK_TOLERANCE
+		}
+		(PlaybackState::Paused, PlaybackState::Paused) => {
+			(current.position - previous.position).abs() > SEEK_TOLERANCE
+		}
+		_ => false,
+	};
+	if seeked {
+		events.push(event(|previous, current| MediaEvent::Seeked {
+			previous,
+			current,
+		}));
+	}
+
+	if !same_artwork(previous.artwork.as_ref(), current.artwork.as_ref()) {
+		events.push(event(|previous, current| MediaEvent::ArtworkChanged {
+			previous,
+			current,
+		}));
+	}
+
+	events
+}
+
+fn same_artwork(a: Option<&ArtworkSource>, b: Option<&ArtworkSource>) -> bool {
+	match (a, b) {
+		(None, None) => true,
+		(Some(ArtworkSource::Bytes(a)), Some(ArtworkSource::Bytes(b))) => a.hash == b.hash,
+		(Some(ArtworkSource::File { path: a }), Some(ArtworkSource::File { path: b })) => a == b,
+		(Some(ArtworkSource::Url { url: a }), Some(ArtworkSource::Url { url: b })) => a == b,
+		_ => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use jiff::{SignedDuration, ToSpan};
+
+	use super::{MediaEvent, MediaTracker, diff};
+	use crate::media::{Artwork, ArtworkSource, Media, PlaybackState};
+
+	fn kinds(events: &[MediaEvent]) -> Vec<&'static str> {
+		events
+			.iter()
+			.map(|event| match event {
+				MediaEvent::TrackChanged { .. } => "track_changed",
+				MediaEvent::Seeked { .. } => "seeked",
+				MediaEvent::Paused { .. } => "paused",
+				MediaEvent::Resumed { .. } => "resumed",
+				MediaEvent::Stopped { .. } => "stopped",
+				MediaEvent::ArtworkChanged { .. } => "artwork_changed",
+			})
+			.collect()
+	}
+
+	#[test]
+	fn reports_track_changes() {
+		let a = Media::fixture("A", PlaybackState::Playing);
+		let b = Media::fixture("B", PlaybackState::Playing);
+
+		assert_eq!(kinds(&diff(None, Some(a.clone()))), ["track_changed"]);
+		let events = diff(Some(a), Some(b));
+		assert_eq!(kinds(&events), ["track_changed"]);
+		let MediaEvent::TrackChanged { previous, current } = &events[0] else {
+			unreachable!();
+		};
+		assert_eq!(previous.as_ref().unwrap().title, "A");
+		assert_eq!(current.title, "B");
+	}
+
+	#[test]
+	fn ignores_unchanged_media() {
+		let a = Media::fixture("A", PlaybackState::Playing);
+		let mut jittered = a.clone();
+		jittered.start += 500.milliseconds();
+
+		assert!(diff(Some(a.clone()), Some(a.clone())).is_empty());
+		assert!(diff(Some(a), Some(jittered)).is_empty());
+		assert!(diff(None, None).is_empty());
+	}
+
+	#[test]
+	fn reports_seeks() {
+		let a = Media::fixture("A", PlaybackState::Playing);
+		let mut seeked = a.clone();
+		seeked.start -= 30.seconds();
+		assert_eq!(kinds(&diff(Some(a), Some(seeked))), ["seeked"]);
+
+		let a = Media::fixture("A", PlaybackState::Paused);
+		let mut seeked = a.clone();
+		seeked.position = SignedDuration::from_secs(60);
+		assert_eq!(kinds(&diff(Some(a), Some(seeked))), ["seeked"]);
+	}
+
+	#[test]
+	fn reports_pausing_resuming_and_stopping() {
+		let playing = Media::fixture("A", PlaybackState::Playing);
+		let paused = Media::fixture("A", PlaybackState::Paused);
+		let stopped = Media::fixture("A", PlaybackState::Stopped);
+
+		let pause = diff(Some(playing.clone()), Some(paused.clone()));
+		assert_eq!(kinds(&pause), ["paused"]);
+		let resume = diff(Some(paused.clone()), Some(playing.clone()));
+		assert_eq!(kinds(&resume), ["resumed"]);
+		let stop = diff(Some(paused), Some(stopped.clone()));
+		assert_eq!(kinds(&stop), ["stopped"]);
+		assert_eq!(kinds(&diff(Some(playing), None)), ["stopped"]);
+		assert!(diff(Some(stopped.clone()), None).is_empty());
+		assert!(diff(None, Some(stopped)).is_empty());
+	}
+
+	#[test]
+	fn reports_artwork_changes() {
+		let a = Media::fixture("A", PlaybackState::Playing);
+		let mut with_artwork = a.clone();
+		with_artwork.artwork = Some(ArtworkSource::Bytes(Artwork::new(
+			"image/png".into(),
+			vec![1, 2, 3].into(),
+		)));
+
+		assert_eq!(
+			kinds(&diff(Some(a), Some(with_artwork))),
+			["artwork_changed"]
+		);
+	}
+
+	#[test]
+	fn tracks_the_last_snapshot() {
+		let mut tracker = MediaTracker::default();
+		let playing = Media::fixture("A", PlaybackState::Playing);
+		let paused = Media::fixture("A", PlaybackState::Paused);
+
+		assert_eq!(
+			kinds(&tracker.update(Some(playing.clone()))),
+			["track_changed"]
+		);
+		assert!(tracker.update(Some(playing)).is_empty());
+		assert_eq!(kinds(&tracker.update(Some(paused))), ["paused"]);
+		assert_eq!(kinds(&tracker.update(None)), ["stopped"]);
+	}
+}
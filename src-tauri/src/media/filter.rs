@@ -0,0 +1,119 @@
+use anyhow::Context;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::Player;
+
+/// Which players drive presence, as glob patterns matched case-insensitively against player IDs
+/// (bundle IDs, AppUserModelIDs or MPRIS bus names) and names.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerSettings {
+	/// If any are given, only these players drive presence. Media from unknown players is
+	/// ignored too.
+	pub allow: Vec<String>,
+	/// These players never drive presence, even if they're allowed.
+	pub block: Vec<String>,
+}
+
+impl PlayerSettings {
+	pub fn filter(&self) -> anyhow::Result<PlayerFilter> {
+		Ok(PlayerFilter {
+			allow: match self.allow.is_empty() {
+				true => None,
+				false => Some(glob_set(&self.allow)?),
+			},
+			block: glob_set(&self.block)?,
+		})
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PlayerFilter {
+	allow: Option<GlobSet>,
+	block: GlobSet,
+}
+
+impl PlayerFilter {
+	pub fn allows(&self, player: Option<&Player>) -> bool {
+		let matches = |set: &GlobSet| {
+			player.is_some_and(|player| set.is_match(&player.id) || set.is_match(&player.name))
+		};
+
+		self.allow.as_ref().is_none_or(matches) && !matches(&self.block)
+	}
+}
+
+fn glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+	let mut builder = GlobSetBuilder::new();
+	for pattern in patterns {
+		let glob = GlobBuilder::new(pattern.trim())
+			.case_insensitive(true)
+			// IDs aren't paths, so `*` should match dots and slashes too
+			.literal_separator(false)
+			.build()
+			.with_context(|| format!("invalid player pattern {pattern:?}"))?;
+		builder.add(glob);
+	}
+	Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::PlayerSettings;
+	use crate::media::Player;
+
+	fn player(id: &str) -> Player {
+		Player::from_id(id).unwrap()
+	}
+
+	#[test]
+	fn allows_everything_by_default() {
+		let filter = PlayerSettings::default().filter().unwrap();
+		assert!(filter.allows(Some(&player("com.spotify.client"))));
+		assert!(filter.allows(None));
+	}
+
+	#[test]
+	fn blocks_matching_players() {
+		let filter = PlayerSettings {
+			block: vec!["com.google.*".into(), "msedge.exe".into()],
+			..Default::default()
+		}
+		.filter()
+		.unwrap();
+
+		assert!(!filter.allows(Some(&player("com.google.Chrome"))));
+		assert!(!filter.allows(Some(&player("MSEdge.exe"))));
+		assert!(filter.allows(Some(&player("com.spotify.client"))));
+		assert!(filter.allows(None));
+	}
+
+	#[test]
+	fn only_allows_listed_players() {
+		let filter = PlayerSettings {
+			allow: vec!["spotify".into(), "com.apple.*".into()],
+			block: vec!["com.apple.Safari".into()],
+		}
+		.filter()
+		.unwrap();
+
+		// by name
+		assert!(filter.allows(Some(&player(
+			"SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify"
+		))));
+		assert!(filter.allows(Some(&player("com.apple.Music"))));
+		assert!(!filter.allows(Some(&player("com.apple.Safari"))));
+		assert!(!filter.allows(Some(&player("firefox.exe"))));
+		assert!(!filter.allows(None));
+	}
+
+	#[test]
+	fn rejects_invalid_patterns() {
+		let settings = PlayerSettings {
+			block: vec!["[".into()],
+			..Default::default()
+		};
+		assert!(settings.filter().is_err());
+	}
+}
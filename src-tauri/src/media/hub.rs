@@ -0,0 +1,103 @@
+use std::sync::Mutex;
+
+use tokio::sync::{broadcast, watch};
+
+use super::{Media, MediaEvent, MediaTracker};
+
+/// How many events a subscriber can fall behind by before it starts missing them.
+const EVENT_CAPACITY: usize = 64;
+
+/// Fans media out from the platform subscription to any number of consumers. Each subscriber gets
+/// its own queue, so a slow one misses events instead of holding up the source or anyone else.
+pub struct MediaHub {
+	media: watch::Sender<Option<Media>>,
+	events: broadcast::Sender<MediaEvent>,
+	tracker: Mutex<MediaTracker>,
+}
+
+impl Default for MediaHub {
+	fn default() -> Self {
+		Self {
+			media: watch::Sender::new(None),
+			events: broadcast::Sender::new(EVENT_CAPACITY),
+			tracker: Mutex::default(),
+		}
+	}
+}
+
+impl MediaHub {
+	/// Publishes the latest media, along with the events describing what changed since the last.
+	pub fn publish(&self, media: Option<Media>) {
+		// holding the tracker keeps events in the same order as the media they came from
+		let mut tracker = self.tracker.lock().unwrap();
+		let events = tracker.update(media.clone());
+		self.media.send_replace(media);
+		for event in events {
+			// there may not be any subscribers yet
+			let _ = self.events.send(event);
+		}
+	}
+
+	/// Starts with the current media, and only ever sees the latest.
+	pub fn watch(&self) -> watch::Receiver<Option<Media>> {
+		self.media.subscribe()
+	}
+
+	/// Sees every event published after subscribing, unless it falls too far behind.
+	pub fn events(&self) -> broadcast::Receiver<MediaEvent> {
+		self.events.subscribe()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use tokio::sync::broadcast::error::TryRecvError;
+
+	use super::{EVENT_CAPACITY, MediaHub};
+	use crate::media::{Media, MediaEvent, PlaybackState};
+
+	#[test]
+	fn late_subscribers_see_the_current_media() {
+		let hub = MediaHub::default();
+		hub.publish(Some(Media::fixture("A", PlaybackState::Playing)));
+
+		let watch = hub.watch();
+		assert_eq!(watch.borrow().as_ref().unwrap().title, "A");
+	}
+
+	#[test]
+	fn every_subscriber_gets_events() {
+		let hub = MediaHub::default();
+		let mut a = hub.events();
+		let mut b = hub.events();
+
+		hub.publish(Some(Media::fixture("A", PlaybackState::Playing)));
+		hub.publish(Some(Media::fixture("A", PlaybackState::Paused)));
+
+		for events in [&mut a, &mut b] {
+			assert!(matches!(
+				events.try_recv(),
+				Ok(MediaEvent::TrackChanged { .. })
+			));
+			assert!(matches!(events.try_recv(), Ok(MediaEvent::Paused { .. })));
+			assert_eq!(events.try_recv().unwrap_err(), TryRecvError::Empty);
+		}
+	}
+
+	#[test]
+	fn slow_subscribers_dont_hold_up_publishing() {
+		let hub = MediaHub::default();
+		let mut slow = hub.events();
+		let mut watch = hub.watch();
+
+		for i in 0..EVENT_CAPACITY * 2 {
+			hub.publish(Some(Media::fixture(&i.to_string(), PlaybackState::Playing)));
+		}
+
+		assert!(matches!(slow.try_recv(), Err(TryRecvError::Lagged(_))));
+		assert!(slow.try_recv().is_ok());
+		assert!(watch.has_changed().unwrap());
+		let last = (EVENT_CAPACITY * 2 - 1).to_string();
+		assert_eq!(watch.borrow_and_update().as_ref().unwrap().title, last);
+	}
+}
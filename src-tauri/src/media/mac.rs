@@ -3,9 +3,13 @@ use std::sync::OnceLock;
 use futures::{TryStream, TryStreamExt};
 use media_remote::MediaRemote;
 
-use tauri::AppHandle;
+use tauri::{AppHandle, Emitter, async_runtime::spawn};
 
-use crate::{error::AppResult, media::Media};
+use crate::{
+	error::AppResult,
+	media::{Media, MediaError},
+	process::ChildHealth,
+};
 
 mod media_remote;
 
@@ -20,6 +24,26 @@ pub async fn get(app: AppHandle) -> AppResult<Option<Media>> {
 pub async fn subscribe(
 	app: AppHandle,
 ) -> anyhow::Result<impl TryStream<Ok = Option<Media>, Error = anyhow::Error>> {
-	let mr = MEDIA_REMOTE.get_or_init(|| MediaRemote::new(app));
-	Ok(mr.subscribe_now_playing_info()?.map_ok(|info| info.into()))
+	let mr = MEDIA_REMOTE.get_or_init(|| MediaRemote::new(app.clone()));
+	let (infos, mut health) = mr.subscribe_now_playing_info();
+
+	// media stops updating while the adapter restarts, which the webview shows like any other
+	// media error
+	spawn(async move {
+		while health.changed().await.is_ok() {
+			let ChildHealth::Restarting {
+				reason, retry_at, ..
+			} = health.borrow_and_update().clone()
+			else {
+				continue;
+			};
+			let error = MediaError {
+				message: format!("media adapter {reason}"),
+				retry_at: Some(retry_at),
+			};
+			let _ = app.emit("media_error", error);
+		}
+	});
+
+	Ok(infos.map_ok(|info| info.into()))
 }
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+
+/// Names of common players, by the IDs the platforms report them with: bundle identifiers on
+/// macOS, and executable names or package family names on Windows.
+const KNOWN_PLAYERS: &[(&str, &str)] = &[
+	("com.spotify.client", "Spotify"),
+	("Spotify.exe", "Spotify"),
+	("SpotifyAB.SpotifyMusic", "Spotify"),
+	("com.apple.Music", "Apple Music"),
+	("AppleInc.AppleMusicWin", "Apple Music"),
+	("com.apple.podcasts", "Apple Podcasts"),
+	("com.apple.TV", "Apple TV"),
+	("Microsoft.ZuneMusic", "Media Player"),
+	("com.tidal.desktop", "TIDAL"),
+	("TIDAL.exe", "TIDAL"),
+	("com.deezer.deezer-desktop", "Deezer"),
+	("Deezer.exe", "Deezer"),
+	("com.amazon.music", "Amazon Music"),
+	("Amazon Music.exe", "Amazon Music"),
+	("org.videolan.vlc", "VLC"),
+	("com.colliderli.iina", "IINA"),
+	("foobar2000.exe", "foobar2000"),
+	("MusicBee.exe", "MusicBee"),
+	("AIMP.exe", "AIMP"),
+	("com.apple.Safari", "Safari"),
+	("com.google.Chrome", "Google Chrome"),
+	("chrome.exe", "Google Chrome"),
+	("com.microsoft.edgemac", "Microsoft Edge"),
+	("msedge.exe", "Microsoft Edge"),
+	("MSEdge", "Microsoft Edge"),
+	("org.mozilla.firefox", "Firefox"),
+	("firefox.exe", "Firefox"),
+	("com.hnc.Discord", "Discord"),
+	("Discord.exe", "Discord"),
+];
+
+/// The app that's playing media.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Player {
+	/// The ID the platform reports the player with, which is stable across versions of the app.
+	pub id: String,
+	pub name: String,
+}
+
+impl Player {
+	pub fn from_id(id: &str) -> Option<Self> {
+		let id = id.trim();
+		if id.is_empty() {
+			return None;
+		}
+
+		let name = KNOWN_PLAYERS
+			.iter()
+			.find(|(known, _)| known.eq_ignore_ascii_case(id) || known_package(known, id))
+			.map(|(_, name)| name.to_string())
+			.unwrap_or_else(|| fallback_name(id));
+
+		Some(Self {
+			id: id.to_owned(),
+			name,
+		})
+	}
+}
+
+/// Packaged Windows apps are reported as `{name}_{publisher}!{app}`, where only the name is
+/// meaningful.
+fn known_package(known: &str, id: &str) -> bool {
+	id.split_once('_')
+		.is_some_and(|(name, _)| known.eq_ignore_ascii_case(name))
+}
+
+/// Makes a name from an unknown ID, like `Foo` from `com.example.Foo` or `Foo.exe`.
+fn fallback_name(id: &str) -> String {
+	let id = id.split_once('!').map_or(id, |(package, _)| package);
+	let id = id.split_once('_').map_or(id, |(name, _)| name);
+	match id.strip_suffix(".exe") {
+		Some(name) => name.to_owned(),
+		None => id.rsplit('.').next().unwrap_or(id).to_owned(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Player;
+
+	fn name(id: &str) -> String {
+		Player::from_id(id).unwrap().name
+	}
+
+	#[test]
+	fn names_known_players() {
+		assert_eq!(name("com.spotify.client"), "Spotify");
+		assert_eq!(name("Spotify.exe"), "Spotify");
+		assert_eq!(name("spotify.exe"), "Spotify");
+		assert_eq!(
+			name("SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify"),
+			"Spotify"
+		);
+		assert_eq!(
+			name("Microsoft.ZuneMusic_8wekyb3d8bbwe!Microsoft.ZuneMusic"),
+			"Media Player"
+		);
+	}
+
+	#[test]
+	fn names_unknown_players_from_their_id() {
+		assert_eq!(name("com.example.Player"), "Player");
+		assert_eq!(name("player.exe"), "player");
+		assert_eq!(name("Example.Player_abc123!App"), "Player");
+		assert_eq!(Player::from_id("  "), None);
+	}
+
+	#[test]
+	fn keeps_the_reported_id() {
+		let player = Player::from_id("SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify").unwrap();
+		assert_eq!(player.id, "SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify");
+	}
+}
@@ -0,0 +1,160 @@
+use std::{pin::pin, time::Duration};
+
+use anyhow::anyhow;
+use futures::{TryStream, TryStreamExt};
+use jiff::Timestamp;
+use serde::Serialize;
+use tokio::time::sleep;
+
+use super::Media;
+use crate::error::AppResult;
+
+/// Why media updates stopped, for the webview to show.
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaError {
+	pub message: String,
+	/// When the subscription is restarted, if the error stopped it.
+	pub retry_at: Option<Timestamp>,
+}
+
+impl MediaError {
+	pub fn new(err: &anyhow::Error, retry_in: Option<Duration>) -> Self {
+		Self {
+			message: format!("{err:#}"),
+			retry_at: retry_in.map(|retry_in| Timestamp::now() + retry_in),
+		}
+	}
+}
+
+/// Doubles the delay between restarts, up to a limit, until the subscription works again.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+	initial: Duration,
+	max: Duration,
+	next: Duration,
+}
+
+impl Default for Backoff {
+	fn default() -> Self {
+		Self::new(Duration::from_secs(1), Duration::from_secs(60))
+	}
+}
+
+impl Backoff {
+	pub fn new(initial: Duration, max: Duration) -> Self {
+		Self {
+			initial,
+			max,
+			next: initial,
+		}
+	}
+
+	pub fn next(&mut self) -> Duration {
+		let delay = self.next;
+		self.next = (self.next * 2).min(self.max);
+		delay
+	}
+
+	pub fn reset(&mut self) {
+		self.next = self.initial;
+	}
+}
+
+/// Keeps a media subscription running, restarting it with backoff whenever it can't be started,
+/// fails or ends. Each error is logged and passed to `report` before restarting.
+pub async fn supervise<S>(
+	mut backoff: Backoff,
+	mut subscribe: impl AsyncFnMut() -> AppResult<S>,
+	mut publish: impl AsyncFnMut(Option<Media>),
+	mut report: impl FnMut(MediaError),
+) -> !
+where
+	S: TryStream<Ok = Option<Media>, Error = anyhow::Error>,
+{
+	loop {
+		let err = match subscribe().await {
+			Ok(subscription) => {
+				let mut subscription = pin!(subscription.into_stream());
+				loop {
+					match subscription.try_next().await {
+						Ok(Some(media)) => {
+							backoff.reset();
+							publish(media).await;
+						}
+						Ok(None) => break anyhow!("media subscription ended"),
+						Err(err) => break err,
+					}
+				}
+			}
+			Err(err) => err.0.context("failed to subscribe to media"),
+		};
+
+		let delay = backoff.next();
+		tracing::warn!(
+			err = format!("{err:#}"),
+			?delay,
+			"restarting media subscription"
+		);
+		report(MediaError::new(&err, Some(delay)));
+		sleep(delay).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use anyhow::anyhow;
+	use futures::stream;
+	use tokio::time::timeout;
+
+	use super::{Backoff, supervise};
+	use crate::{
+		error::AppResult,
+		media::{Media, PlaybackState},
+	};
+
+	#[test]
+	fn backs_off_exponentially_up_to_the_limit() {
+		let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+		let delays: Vec<_> = (0..5).map(|_| backoff.next().as_secs()).collect();
+		assert_eq!(delays, [1, 2, 4, 5, 5]);
+
+		backoff.reset();
+		assert_eq!(backoff.next(), Duration::from_secs(1));
+	}
+
+	#[tokio::test]
+	async fn restarts_failed_subscriptions() {
+		let mut attempts = 0;
+		let mut published = Vec::new();
+		let mut errors = Vec::new();
+
+		let _ = timeout(
+			Duration::from_millis(200),
+			supervise(
+				Backoff::new(Duration::from_millis(1), Duration::from_millis(1)),
+				async || -> AppResult<_> {
+					attempts += 1;
+					match attempts {
+						1 => Err(anyhow!("no session").into()),
+						2 => Ok(stream::iter(vec![
+							Ok(Some(Media::fixture("A", PlaybackState::Playing))),
+							Err(anyhow!("bad output")),
+						])),
+						_ => Ok(stream::iter(vec![Ok(None)])),
+					}
+				},
+				async |media| published.push(media.map(|media| media.title)),
+				|err| errors.push(err.message),
+			),
+		)
+		.await;
+
+		assert!(attempts > 3);
+		assert_eq!(published[..2], [Some("A".to_owned()), None]);
+		assert_eq!(errors[0], "failed to subscribe to media: no session");
+		assert_eq!(errors[1], "bad output");
+		assert_eq!(errors[2], "media subscription ended");
+	}
+}
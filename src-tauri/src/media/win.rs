@@ -1,6 +1,5 @@
 use std::thread;
 
-use blake3::hash;
 use futures::TryStream;
 use jiff::{SignedDuration, Timestamp};
 use tauri::{
@@ -20,9 +19,12 @@ use windows::{
 	},
 	Storage::Streams::{DataReader, IRandomAccessStreamWithContentType},
 };
-use windows_core::Ref;
+use windows_core::{HSTRING, Ref};
 
-use crate::{error::AppResult, media::Media};
+use crate::{
+	error::AppResult,
+	media::{self, Artwork, ArtworkSource, Media, PlaybackState, Player},
+};
 
 /// The universal time epoch is midnight on January 1, 1601 in the Gregorian calendar
 fn universal_epoch() -> Timestamp {
@@ -44,6 +46,11 @@ fn from_time_span(time_span: TimeSpan) -> SignedDuration {
 	SignedDuration::from_nanos(time_span.Duration * 100)
 }
 
+/// Missing properties are reported as empty strings.
+fn non_empty(value: HSTRING) -> Option<String> {
+	(!value.is_empty()).then(|| value.to_string_lossy())
+}
+
 fn read_stream_to_vec(
 	stream: &IRandomAccessStreamWithContentType,
 ) -> windows_core::Result<Vec<u8>> {
@@ -90,19 +97,19 @@ fn subscribe_session(
 	let tx2 = tx.clone();
 	let session_update =
 		move |session: Ref<GlobalSystemMediaTransportControlsSession>| -> windows_core::Result<()> {
-			match &*session {
-				Some(session) => {
-					if session.GetPlaybackInfo()?.PlaybackStatus()?
-						== GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing
-					{
-						let properties = session.TryGetMediaPropertiesAsync()?.get()?;
-						let timeline = session.GetTimelineProperties()?;
-
-						let _ =
-							tx2.blocking_send(Ok(Some(Media::from_windows(timeline, properties)?)));
-					} else {
-						let _ = tx2.blocking_send(Ok(None));
-					}
+			let state = match &*session {
+				Some(session) => playback_state(session)?.map(|state| (session, state)),
+				None => None,
+			};
+			match state {
+				Some((session, state)) => {
+					let properties = session.TryGetMediaPropertiesAsync()?.get()?;
+					let timeline = session.GetTimelineProperties()?;
+					let rate = playback_rate(session);
+					let media =
+						Media::from_windows(timeline, properties, player(session), state, rate)?;
+
+					let _ = tx2.blocking_send(Ok(Some(media)));
 				}
 				None => {
 					let _ = tx2.blocking_send(Ok(None));
@@ -135,43 +142,108 @@ fn subscribe_session(
 	Ok(())
 }
 
+/// `None` once the player has closed its session.
+fn playback_state(
+	session: &GlobalSystemMediaTransportControlsSession,
+) -> windows_core::Result<Option<PlaybackState>> {
+	let status = session.GetPlaybackInfo()?.PlaybackStatus()?;
+	Ok(match status {
+		GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing => {
+			Some(PlaybackState::Playing)
+		}
+		GlobalSystemMediaTransportControlsSessionPlaybackStatus::Paused => {
+			Some(PlaybackState::Paused)
+		}
+		GlobalSystemMediaTransportControlsSessionPlaybackStatus::Closed => None,
+		_ => Some(PlaybackState::Stopped),
+	})
+}
+
+/// Not every player reports its rate.
+fn playback_rate(session: &GlobalSystemMediaTransportControlsSession) -> f64 {
+	let rate = session
+		.GetPlaybackInfo()
+		.and_then(|info| info.PlaybackRate())
+		.and_then(|rate| rate.Value());
+	media::playback_rate(rate.ok())
+}
+
+/// Identifies the player by its AppUserModelID, which is the executable name for unpackaged apps.
+fn player(session: &GlobalSystemMediaTransportControlsSession) -> Option<Player> {
+	let id = session.SourceAppUserModelId().ok()?;
+	Player::from_id(&id.to_string_lossy())
+}
+
 pub async fn get(_app: AppHandle) -> AppResult<Option<Media>> {
 	let session = GlobalSystemMediaTransportControlsSessionManager::RequestAsync()?
 		.await?
 		.GetCurrentSession()?;
 
+	let Some(state) = playback_state(&session)? else {
+		return Ok(None);
+	};
 	let properties = session.TryGetMediaPropertiesAsync()?.await?;
 	let timeline = session.GetTimelineProperties()?;
+	let player = player(&session);
+	let rate = playback_rate(&session);
 
-	Ok(spawn_blocking(|| Media::from_windows(timeline, properties))
-		.await?
-		.map(Some)?)
+	Ok(
+		spawn_blocking(move || Media::from_windows(timeline, properties, player, state, rate))
+			.await?
+			.map(Some)?,
+	)
 }
 
 impl Media {
 	fn from_windows(
 		timeline: GlobalSystemMediaTransportControlsSessionTimelineProperties,
 		properties: GlobalSystemMediaTransportControlsSessionMediaProperties,
+		player: Option<Player>,
+		state: PlaybackState,
+		rate: f64,
 	) -> windows_core::Result<Self> {
 		let last_updated = from_date_time(timeline.LastUpdatedTime()?);
 		let elapsed = from_time_span(timeline.Position()?);
 		let start_duration = from_time_span(timeline.StartTime()?);
-		let start = last_updated - elapsed + start_duration;
-		let end = start + from_time_span(timeline.EndTime()?) - start_duration;
-
-		let artwork_stream = properties.Thumbnail()?.OpenReadAsync()?.get()?;
-		let artwork_mime = artwork_stream.ContentType()?.to_string_lossy();
-		let artwork_bytes = read_stream_to_vec(&artwork_stream)?;
-		let artwork_hash = hash(&artwork_bytes).to_hex().to_string();
+		// live streams report an end time equal to the start time
+		let duration = from_time_span(timeline.EndTime()?) - start_duration;
+		let (start, end) =
+			media::timeline(last_updated, elapsed - start_duration, Some(duration), rate);
+
+		let artwork = match properties.Thumbnail() {
+			Ok(thumbnail) => {
+				let stream = thumbnail.OpenReadAsync()?.get()?;
+				let mime = stream.ContentType()?.to_string_lossy();
+				Some(ArtworkSource::Bytes(Artwork::new(
+					mime,
+					read_stream_to_vec(&stream)?.into(),
+				)))
+			}
+			// players without artwork don't set a thumbnail
+			Err(_) => None,
+		};
 
 		Ok(Media {
 			title: properties.Title()?.to_string_lossy(),
 			artist: properties.Artist()?.to_string_lossy(),
+			player,
+			album: non_empty(properties.AlbumTitle()?),
+			album_artist: non_empty(properties.AlbumArtist()?),
+			// unknown track numbers are reported as 0
+			track_number: u32::try_from(properties.TrackNumber()?)
+				.ok()
+				.filter(|track_number| *track_number > 0),
+			genres: properties
+				.Genres()?
+				.into_iter()
+				.map(|genre| genre.to_string_lossy())
+				.collect(),
+			state,
+			rate,
+			position: elapsed - start_duration,
 			start,
 			end,
-			artwork_mime,
-			artwork_bytes,
-			artwork_hash,
+			artwork,
 		})
 	}
 }
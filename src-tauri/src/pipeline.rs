@@ -0,0 +1,164 @@
+use reqwest::Url;
+use tauri::{AppHandle, Manager, async_runtime::spawn_blocking};
+use tokio::fs;
+use tracing::Level;
+
+use crate::{
+	artwork::{ArtworkCache, ArtworkProcessor, sniff_mime},
+	error::AppResult,
+	media::{Artwork, ArtworkSource, Media},
+	state::{PlayerFilterState, SettingsState},
+};
+
+/// Prepares media reported by the platform backends before anything else sees it.
+#[tracing::instrument(skip_all, err, level = Level::DEBUG)]
+pub async fn prepare(app: &AppHandle, media: Option<Media>) -> AppResult<Option<Media>> {
+	let Some(mut media) = media else {
+		return Ok(None);
+	};
+
+	let filter = app.state::<PlayerFilterState>();
+	if !filter.read().await.allows(media.player.as_ref()) {
+		tracing::debug!(player = ?media.player, "ignoring media from filtered player");
+		return Ok(None);
+	}
+
+	let mut artwork = match media.artwork.take() {
+		None => return Ok(Some(media)),
+		Some(source) => match resolve(source).await {
+			Some(ArtworkSource::Bytes(artwork)) => artwork,
+			// remote artwork is shown as-is
+			source => {
+				return Ok(Some(Media {
+					artwork: source,
+					..media
+				}));
+			}
+		},
+	};
+
+	match sniff_mime(&artwork.bytes) {
+		Some(mime) if mime != artwork.mime => {
+			tracing::debug!(reported = %artwork.mime, mime, "correcting artwork type");
+			artwork.mime = mime.to_owned();
+		}
+		Some(_) => {}
+		None => {
+			tracing::warn!(reported = %artwork.mime, "artwork isn't a recognized image, dropping it");
+			return Ok(Some(media));
+		}
+	}
+
+	let settings = app.state::<SettingsState>();
+	let settings = settings.read().await.artwork.processing.clone();
+
+	let handle = app.clone();
+	let processed = spawn_blocking(move || {
+		let processor = handle.state::<ArtworkProcessor>();
+		let cache = handle.state::<ArtworkCache>();
+		processor.process(&artwork, &settings, &cache)
+	})
+	.await?;
+
+	// unprocessed artwork may be too big for the host or still carry metadata, so it's better
+	// to show the fallback image instead
+	let artwork = match processed {
+		Ok(processed) => processed,
+		Err(err) => {
+			tracing::warn!(%err, "failed to process artwork, dropping it");
+			return Ok(Some(media));
+		}
+	};
+
+	Ok(Some(Media {
+		artwork: Some(ArtworkSource::Bytes(artwork)),
+		..media
+	}))
+}
+
+/// Reads local artwork into bytes, leaving either bytes or an `https` URL that Discord can fetch
+/// itself. Returns `None` for artwork that can't be shown.
+async fn resolve(source: ArtworkSource) -> Option<ArtworkSource> {
+	let path = match source {
+		ArtworkSource::Bytes(_) => return Some(source),
+		ArtworkSource::File { path } => path,
+		ArtworkSource::Url { url } => {
+			let parsed = Url::parse(&url).ok();
+			match parsed.as_ref().map(Url::scheme) {
+				Some("https") => return Some(ArtworkSource::Url { url }),
+				Some("file") => parsed.and_then(|url| url.to_file_path().ok())?,
+				_ => {
+					tracing::warn!(url, "ignoring artwork that isn't an https or file URL");
+					return None;
+				}
+			}
+		}
+	};
+
+	match fs::read(&path).await {
+		Ok(bytes) => Some(ArtworkSource::Bytes(Artwork::new(
+			sniff_mime(&bytes)
+				.unwrap_or("application/octet-stream")
+				.to_owned(),
+			bytes.into(),
+		))),
+		Err(err) => {
+			tracing::warn!(%err, ?path, "failed to read artwork");
+			None
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use reqwest::Url;
+
+	use super::resolve;
+	use crate::{
+		media::ArtworkSource,
+		testing::{PNG, TempDir},
+	};
+
+	async fn resolve_url(url: &str) -> Option<ArtworkSource> {
+		resolve(ArtworkSource::Url { url: url.into() }).await
+	}
+
+	#[tokio::test]
+	async fn shows_https_artwork_as_is() {
+		let url = "https://example.com/artwork.png";
+		assert!(matches!(
+			resolve_url(url).await,
+			Some(ArtworkSource::Url { url: resolved }) if resolved == url
+		));
+
+		assert!(
+			resolve_url("http://example.com/artwork.png")
+				.await
+				.is_none()
+		);
+		assert!(resolve_url("data:image/png;base64,AAAA").await.is_none());
+		assert!(resolve_url("not a url").await.is_none());
+	}
+
+	#[tokio::test]
+	async fn reads_local_artwork() {
+		let dir = TempDir::default();
+		let path = dir.join("artwork.png");
+		std::fs::write(&path, PNG).unwrap();
+
+		let file = resolve(ArtworkSource::File { path: path.clone() }).await;
+		let file_url = resolve_url(Url::from_file_path(&path).unwrap().as_str()).await;
+		std::fs::remove_file(&path).unwrap();
+
+		for resolved in [file, file_url] {
+			let Some(ArtworkSource::Bytes(artwork)) = resolved else {
+				panic!("expected local artwork to be read");
+			};
+			assert_eq!(artwork.mime, "image/png");
+			assert_eq!(&artwork.bytes[..], PNG);
+		}
+
+		let missing = resolve(ArtworkSource::File { path }).await;
+		assert!(missing.is_none());
+	}
+}
@@ -0,0 +1,388 @@
+use std::time::Duration;
+
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, async_runtime::spawn};
+use tokio::{
+	select,
+	time::{sleep, timeout},
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::Level;
+
+use crate::{
+	api::{Api, ArtworkHost, UploadLedger},
+	error::AppResult,
+	media::{Artwork, ArtworkSource, Media, PlaybackState},
+	rpc::{Activity, ActivityAssets, ActivityTimestamps},
+	state::{ApiState, PresenceState, RpcState, SettingsState},
+};
+
+const UPLOAD_ATTEMPTS: u32 = 3;
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+const UPLOAD_BACKOFF: Duration = Duration::from_secs(2);
+/// Live streams don't end, so their artwork is uploaded to expire after this long, and refreshed
+/// well before then for as long as they keep playing.
+const LIVE_ARTWORK_TTL: Duration = Duration::from_secs(30 * 60);
+const LIVE_ARTWORK_REFRESH: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PresenceSettings {
+	/// What happens to the activity while playback is paused.
+	pub paused: PausedBehavior,
+	/// An asset key or URL shown as the small image while paused.
+	pub paused_image: Option<String>,
+	/// How long paused media is shown for with [`PausedBehavior::ClearWhenIdle`].
+	pub idle_timeout_secs: u64,
+}
+
+impl Default for PresenceSettings {
+	fn default() -> Self {
+		Self {
+			paused: PausedBehavior::Clear,
+			paused_image: None,
+			idle_timeout_secs: 5 * 60,
+		}
+	}
+}
+
+impl PresenceSettings {
+	fn shows(&self, state: PlaybackState) -> bool {
+		match state {
+			PlaybackState::Playing => true,
+			PlaybackState::Paused => self.paused != PausedBehavior::Clear,
+			PlaybackState::Stopped => false,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PausedBehavior {
+	/// Clears the activity as soon as playback is paused.
+	Clear,
+	/// Keeps showing paused media, without timestamps.
+	Show,
+	/// Shows paused media until it's been paused for the idle timeout.
+	ClearWhenIdle,
+}
+
+/// What's currently shown in Discord, and the artwork upload backing it.
+#[derive(Default)]
+pub struct Presence {
+	media: Option<Media>,
+	/// The hash of artwork that's known to be uploaded.
+	uploaded: Option<String>,
+	upload: Option<Upload>,
+	/// Keeps the artwork of a playing live stream from expiring.
+	refresh: Option<Upload>,
+	/// Clears the activity once paused media has been idle for too long.
+	idle: Option<DropGuard>,
+	/// Paused media that idled out, which stays cleared until it resumes or the track changes.
+	idled: Option<Media>,
+}
+
+impl Presence {
+	/// Whether `media` is still paused on a track that idled out, forgetting the track otherwise.
+	fn idled_out(&mut self, media: &Media) -> bool {
+		let idled_out = media.state == PlaybackState::Paused
+			&& self
+				.idled
+				.as_ref()
+				.is_some_and(|idled| idled.same_track(media));
+		if !idled_out {
+			self.idled = None;
+		}
+		idled_out
+	}
+}
+
+struct Upload {
+	hash: String,
+	_cancel: DropGuard,
+}
+
+/// Sets the activity for `media` straight away, using the fallback image until its artwork has
+/// been uploaded in the background, at which point the activity is updated with the artwork. Remote
+/// artwork is shown directly, and media without artwork keeps the fallback image. Does nothing
+/// until connected.
+#[tracing::instrument(skip(app), err, level = Level::INFO)]
+pub async fn set(app: &AppHandle, media: Option<Media>) -> AppResult<()> {
+	let rpc = app.state::<RpcState>();
+	let rpc = rpc.lock().await;
+	let Some(rpc) = rpc.as_ref() else {
+		return Ok(());
+	};
+
+	let presence = app.state::<PresenceState>();
+	let mut presence = presence.lock().await;
+
+	let settings = app.state::<SettingsState>().read().await.presence.clone();
+	let Some(media) = media.filter(|media| settings.shows(media.state)) else {
+		*presence = Presence::default();
+		rpc.clear_activity().await;
+		return Ok(());
+	};
+	if presence.idled_out(&media) {
+		return Ok(());
+	}
+
+	let api = app.state::<ApiState>().read().await.clone();
+	let large_image = match (&media.artwork, &api) {
+		(Some(ArtworkSource::Url { url }), _) => Some(url.clone()),
+		(Some(ArtworkSource::Bytes(artwork)), Some(api))
+			if presence.uploaded.as_ref() == Some(&artwork.hash) =>
+		{
+			Some(api.artwork_url(&artwork.hash))
+		}
+		_ => fallback_image(app, &media).await,
+	};
+	rpc.set_activity(activity(&media, large_image, &settings))
+		.await;
+
+	// the idle timer keeps running across updates while paused, until playback resumes
+	if media.state != PlaybackState::Paused {
+		presence.idle = None;
+	} else if settings.paused == PausedBehavior::ClearWhenIdle && presence.idle.is_none() {
+		let cancel = CancellationToken::new();
+		presence.idle = Some(cancel.clone().drop_guard());
+		let timeout = Duration::from_secs(settings.idle_timeout_secs);
+		spawn(clear_when_idle(app.clone(), timeout, cancel));
+	}
+
+	// live artwork only needs refreshing while it's playing
+	let live_artwork = match &media.artwork {
+		Some(ArtworkSource::Bytes(artwork))
+			if media.state == PlaybackState::Playing && media.end.is_none() =>
+		{
+			Some(&artwork.hash)
+		}
+		_ => None,
+	};
+	if presence
+		.refresh
+		.as_ref()
+		.is_some_and(|refresh| Some(&refresh.hash) != live_artwork)
+	{
+		presence.refresh = None;
+	}
+
+	// uploads expire when the track ends, which isn't known while paused
+	if media.state == PlaybackState::Playing
+		&& let Some(api) = api
+		&& let Some(ArtworkSource::Bytes(artwork)) = &media.artwork
+		&& !presence
+			.upload
+			.as_ref()
+			.is_some_and(|upload| upload.hash == artwork.hash)
+		// live artwork is kept uploaded by its refresh task instead
+		&& !presence
+			.refresh
+			.as_ref()
+			.is_some_and(|refresh| refresh.hash == artwork.hash)
+	{
+		let cancel = CancellationToken::new();
+		presence.upload = Some(Upload {
+			hash: artwork.hash.clone(),
+			_cancel: cancel.clone().drop_guard(),
+		});
+		spawn(upload(app.clone(), api, artwork.clone(), media.end, cancel));
+	}
+
+	presence.media = Some(media);
+	Ok(())
+}
+
+/// Uploads artwork with retries, then updates the activity if the media is still current.
+/// Artwork for media without an end is kept uploaded by [`refresh`] once it's shown.
+#[tracing::instrument(skip_all, fields(hash = %artwork.hash))]
+async fn upload(
+	app: AppHandle,
+	api: Api,
+	artwork: Artwork,
+	end: Option<Timestamp>,
+	cancel: CancellationToken,
+) {
+	let expires_at = end.unwrap_or_else(|| Timestamp::now() + LIVE_ARTWORK_TTL);
+	let uploaded = select! {
+		_ = cancel.cancelled() => return,
+		uploaded = upload_with_retries(&app, &api, &artwork, expires_at) => uploaded,
+	};
+
+	let rpc = app.state::<RpcState>();
+	let rpc = rpc.lock().await;
+	let presence = app.state::<PresenceState>();
+	let mut presence = presence.lock().await;
+
+	if presence
+		.upload
+		.as_ref()
+		.is_some_and(|upload| upload.hash == artwork.hash)
+	{
+		presence.upload = None;
+	}
+
+	let Some(current) = presence.media.as_ref().filter(|current| {
+		matches!(&current.artwork, Some(ArtworkSource::Bytes(current)) if current.hash == artwork.hash)
+	}) else {
+		return;
+	};
+
+	if !uploaded {
+		return;
+	}
+
+	let live = current.end.is_none() && current.state == PlaybackState::Playing;
+	if presence.uploaded.as_ref() != Some(&artwork.hash) {
+		if let Some(rpc) = rpc.as_ref() {
+			let settings = app.state::<SettingsState>().read().await.presence.clone();
+			let large_image = Some(api.artwork_url(&artwork.hash));
+			rpc.set_activity(activity(current, large_image, &settings))
+				.await;
+		}
+		presence.uploaded = Some(artwork.hash.clone());
+	}
+
+	if live {
+		let cancel = CancellationToken::new();
+		presence.refresh = Some(Upload {
+			hash: artwork.hash.clone(),
+			_cancel: cancel.clone().drop_guard(),
+		});
+		spawn(refresh(app.clone(), api, artwork, cancel));
+	}
+}
+
+/// Pushes back the expiry of a live stream's artwork until it stops playing.
+#[tracing::instrument(skip_all, fields(hash = %artwork.hash))]
+async fn refresh(app: AppHandle, api: Api, artwork: Artwork, cancel: CancellationToken) {
+	loop {
+		select! {
+			_ = cancel.cancelled() => return,
+			_ = sleep(LIVE_ARTWORK_REFRESH) => {}
+		}
+
+		let expires_at = Timestamp::now() + LIVE_ARTWORK_TTL;
+		select! {
+			_ = cancel.cancelled() => return,
+			_ = upload_with_retries(&app, &api, &artwork, expires_at) => {}
+		}
+	}
+}
+
+async fn upload_with_retries(
+	app: &AppHandle,
+	api: &Api,
+	artwork: &Artwork,
+	expires_at: Timestamp,
+) -> bool {
+	let ledger = app.state::<UploadLedger>();
+	for attempt in 1..=UPLOAD_ATTEMPTS {
+		let result = timeout(
+			UPLOAD_TIMEOUT,
+			api.set_artwork(&ledger, &artwork.hash, artwork.bytes.clone(), expires_at),
+		)
+		.await
+		.unwrap_or_else(|elapsed| Err(elapsed.into()));
+
+		match result {
+			Ok(()) => return true,
+			Err(err) => tracing::warn!(%err, attempt, "failed to upload artwork"),
+		}
+
+		if attempt < UPLOAD_ATTEMPTS {
+			sleep(UPLOAD_BACKOFF * attempt).await;
+		}
+	}
+	false
+}
+
+/// Clears the activity if playback stays paused for `timeout`.
+#[tracing::instrument(skip(app, cancel))]
+async fn clear_when_idle(app: AppHandle, timeout: Duration, cancel: CancellationToken) {
+	select! {
+		_ = cancel.cancelled() => return,
+		_ = sleep(timeout) => {}
+	}
+
+	let rpc = app.state::<RpcState>();
+	let rpc = rpc.lock().await;
+	let presence = app.state::<PresenceState>();
+	let mut presence = presence.lock().await;
+
+	// playback may have resumed while waiting for the locks
+	if cancel.is_cancelled() {
+		return;
+	}
+
+	tracing::debug!("clearing idle activity");
+	*presence = Presence {
+		idled: presence.media.take(),
+		..Default::default()
+	};
+	if let Some(rpc) = rpc.as_ref() {
+		rpc.clear_activity().await;
+	}
+}
+
+/// The player's own fallback image if it has one, otherwise the general fallback image.
+async fn fallback_image(app: &AppHandle, media: &Media) -> Option<String> {
+	let settings = app.state::<SettingsState>();
+	let settings = settings.read().await;
+	media
+		.player
+		.as_ref()
+		.and_then(|player| settings.artwork.player_images.get(&player.id))
+		.or(settings.artwork.fallback_image.as_ref())
+		.cloned()
+}
+
+fn activity(media: &Media, large_image: Option<String>, settings: &PresenceSettings) -> Activity {
+	let paused = media.state == PlaybackState::Paused;
+	Activity {
+		details: Some(media.title.clone()),
+		state: Some(media.artist.clone()),
+		r#type: 2,
+		// Discord would keep the progress bar moving while paused
+		timestamps: (!paused).then(|| ActivityTimestamps {
+			start: Some(media.start),
+			end: media.end,
+		}),
+		assets: Some(ActivityAssets {
+			large_image,
+			large_text: media.album.clone(),
+			small_image: settings.paused_image.clone().filter(|_| paused),
+			small_text: paused.then(|| "Paused".to_owned()),
+			..Default::default()
+		}),
+		status_display_type: Some(1),
+		..Default::default()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Presence;
+	use crate::media::{Media, PlaybackState};
+
+	#[test]
+	fn stays_idle_until_resumed_or_changed() {
+		let paused = Media::fixture("A", PlaybackState::Paused);
+		let mut presence = Presence {
+			idled: Some(paused.clone()),
+			..Default::default()
+		};
+
+		// players report paused media again on every update and reconnect
+		assert!(presence.idled_out(&paused));
+		assert!(presence.idled_out(&paused));
+
+		assert!(!presence.idled_out(&Media::fixture("A", PlaybackState::Playing)));
+		assert!(!presence.idled_out(&paused));
+
+		presence.idled = Some(paused);
+		assert!(!presence.idled_out(&Media::fixture("B", PlaybackState::Paused)));
+		assert!(presence.idled.is_none());
+	}
+}
@@ -0,0 +1,237 @@
+use std::{ffi::OsString, io, path::PathBuf, process::Stdio};
+
+use futures::{Stream, stream};
+use jiff::Timestamp;
+use serde::Serialize;
+use tauri::async_runtime::spawn;
+use tokio::{
+	io::{AsyncBufReadExt, BufReader, Lines},
+	process::{Child, ChildStderr, ChildStdout, Command},
+	sync::watch,
+	time::sleep,
+};
+
+use crate::media::Backoff;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ChildHealth {
+	Starting,
+	Running {
+		pid: Option<u32>,
+	},
+	/// The child exited or couldn't be started, and is started again at `retry_at`.
+	Restarting {
+		restarts: u32,
+		reason: String,
+		retry_at: Timestamp,
+	},
+}
+
+/// A long-running child process whose stdout is streamed line by line. The child is restarted with
+/// backoff whenever it exits, its stderr is logged, and it's killed once the stream is dropped.
+pub struct SupervisedChild {
+	name: String,
+	program: PathBuf,
+	args: Vec<OsString>,
+	backoff: Backoff,
+	restarts: u32,
+	health: watch::Sender<ChildHealth>,
+}
+
+struct Running {
+	child: Child,
+	stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl SupervisedChild {
+	pub fn new(
+		name: impl Into<String>,
+		program: impl Into<PathBuf>,
+		args: impl IntoIterator<Item = impl Into<OsString>>,
+		backoff: Backoff,
+	) -> Self {
+		Self {
+			name: name.into(),
+			program: program.into(),
+			args: args.into_iter().map(Into::into).collect(),
+			backoff,
+			restarts: 0,
+			health: watch::Sender::new(ChildHealth::Starting),
+		}
+	}
+
+	pub fn health(&self) -> watch::Receiver<ChildHealth> {
+		self.health.subscribe()
+	}
+
+	/// Starts the child once the stream is first polled. The stream never ends by itself.
+	pub fn lines(self) -> impl Stream<Item = String> {
+		stream::unfold((self, None), |(mut this, mut running)| async move {
+			loop {
+				let mut current: Running = match running.take() {
+					Some(current) => current,
+					None => match this.spawn() {
+						Ok(current) => current,
+						Err(err) => {
+							this.restart(format!("failed to start: {err}")).await;
+							continue;
+						}
+					},
+				};
+
+				match current.stdout.next_line().await {
+					Ok(Some(line)) => {
+						this.backoff.reset();
+						return Some((line, (this, Some(current))));
+					}
+					Ok(None) => {
+						let reason = match current.child.wait().await {
+							Ok(status) => format!("exited with {status}"),
+							Err(err) => format!("exited: {err}"),
+						};
+						this.restart(reason).await;
+					}
+					Err(err) => {
+						// kills the child before it's restarted
+						drop(current);
+						this.restart(format!("failed to read output: {err}")).await;
+					}
+				}
+			}
+		})
+	}
+
+	fn spawn(&self) -> io::Result<Running> {
+		let mut child = Command::new(&self.program)
+			.args(&self.args)
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.kill_on_drop(true)
+			.spawn()?;
+
+		let stdout = child.stdout.take().expect("stdout is piped");
+		if let Some(stderr) = child.stderr.take() {
+			spawn(log_stderr(self.name.clone(), stderr));
+		}
+
+		tracing::debug!(name = %self.name, pid = child.id(), "started child process");
+		self.health
+			.send_replace(ChildHealth::Running { pid: child.id() });
+		Ok(Running {
+			child,
+			stdout: BufReader::new(stdout).lines(),
+		})
+	}
+
+	async fn restart(&mut self, reason: String) {
+		let delay = self.backoff.next();
+		self.restarts += 1;
+		tracing::warn!(name = %self.name, %reason, ?delay, "restarting child process");
+		self.health.send_replace(ChildHealth::Restarting {
+			restarts: self.restarts,
+			reason,
+			retry_at: Timestamp::now() + delay,
+		});
+		sleep(delay).await;
+	}
+}
+
+#[tracing::instrument(skip(stderr))]
+async fn log_stderr(name: String, stderr: ChildStderr) {
+	let mut lines = BufReader::new(stderr).lines();
+	while let Ok(Some(line)) = lines.next_line().await {
+		tracing::warn!("{line}");
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{pin::pin, time::Duration};
+
+	use futures::StreamExt;
+	use tokio::{
+		select,
+		time::{sleep, timeout},
+	};
+
+	use super::{ChildHealth, SupervisedChild};
+	use crate::media::Backoff;
+
+	fn backoff() -> Backoff {
+		Backoff::new(Duration::from_millis(10), Duration::from_millis(10))
+	}
+
+	fn sh(script: &str) -> SupervisedChild {
+		SupervisedChild::new("test", "sh", ["-c", script], backoff())
+	}
+
+	#[tokio::test]
+	async fn restarts_children_that_exit() {
+		let child = sh("echo a; echo oops >&2; echo b; exit 3");
+		let health = child.health();
+		let lines: Vec<_> = child.lines().take(5).collect().await;
+
+		assert_eq!(lines, ["a", "b", "a", "b", "a"]);
+		let ChildHealth::Running { pid } = *health.borrow() else {
+			panic!("expected the child to be running");
+		};
+		assert!(pid.is_some());
+	}
+
+	#[tokio::test]
+	async fn reports_restarts_in_health() {
+		let child = sh("exit 3");
+		let mut health = child.health();
+		let mut lines = pin!(child.lines());
+
+		// the child is briefly running between restarts, so wait for a restart to be reported
+		let restarted = health.wait_for(
+			|health| matches!(health, ChildHealth::Restarting { restarts, .. } if *restarts > 1),
+		);
+		let health = select! {
+			_ = lines.next() => panic!("the stream only ever restarts, so it never yields"),
+			health = timeout(Duration::from_secs(5), restarted) => health.unwrap().unwrap().clone(),
+		};
+
+		let ChildHealth::Restarting { reason, .. } = health else {
+			unreachable!();
+		};
+		assert!(reason.contains("exit status: 3"), "{reason}");
+	}
+
+	#[tokio::test]
+	async fn reports_children_that_fail_to_start() {
+		let child = SupervisedChild::new("test", "/nonexistent", [""; 0], backoff());
+		let health = child.health();
+		let mut lines = pin!(child.lines());
+
+		assert!(
+			timeout(Duration::from_millis(100), lines.next())
+				.await
+				.is_err()
+		);
+		assert!(matches!(
+			&*health.borrow(),
+			ChildHealth::Restarting { reason, .. } if reason.starts_with("failed to start")
+		));
+	}
+
+	#[cfg(target_os = "linux")]
+	#[tokio::test]
+	async fn kills_the_child_when_dropped() {
+		let mut lines = Box::pin(sh("echo $$; exec sleep 60").lines());
+		let pid = lines.next().await.unwrap();
+		drop(lines);
+
+		// killed children are reaped in the background
+		for _ in 0..50 {
+			match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+				Ok(stat) if !stat.contains(") Z ") => sleep(Duration::from_millis(10)).await,
+				_ => return,
+			}
+		}
+		panic!("child {pid} is still running");
+	}
+}
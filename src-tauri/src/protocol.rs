@@ -0,0 +1,34 @@
+use tauri::{
+	Manager, Runtime, UriSchemeContext, UriSchemeResponder,
+	async_runtime::spawn_blocking,
+	http::{Request, Response, StatusCode, header},
+};
+
+use crate::artwork::ArtworkCache;
+
+pub const ARTWORK_SCHEME: &str = "artwork";
+
+/// Serves artwork from the artwork cache at `artwork://localhost/<hash>`. The cache may have to
+/// read artwork from disk, so this is done on a blocking thread.
+pub fn artwork<R: Runtime>(
+	ctx: UriSchemeContext<'_, R>,
+	request: Request<Vec<u8>>,
+	responder: UriSchemeResponder,
+) {
+	let app = ctx.app_handle().clone();
+	let hash = request.uri().path().trim_start_matches('/').to_owned();
+
+	spawn_blocking(move || {
+		let response = match app.state::<ArtworkCache>().get(&hash) {
+			Some(artwork) => Response::builder()
+				.header(header::CONTENT_TYPE, artwork.mime)
+				// artwork is content-addressed, so it never changes
+				.header(header::CACHE_CONTROL, "public, max-age=31536000, immutable")
+				.body(Vec::from(artwork.bytes)),
+			None => Response::builder()
+				.status(StatusCode::NOT_FOUND)
+				.body(Vec::new()),
+		};
+		responder.respond(response.unwrap());
+	});
+}
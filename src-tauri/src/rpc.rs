@@ -199,9 +199,16 @@ where
 
 #[derive(Debug, Serialize)]
 pub struct ActivityTimestamps {
-	#[serde(serialize_with = "activity_timestamp_serializer")]
+	#[serde(
+		serialize_with = "activity_timestamp_serializer",
+		skip_serializing_if = "Option::is_none"
+	)]
 	pub start: Option<Timestamp>,
-	#[serde(serialize_with = "activity_timestamp_serializer")]
+	/// `None` for live streams.
+	#[serde(
+		serialize_with = "activity_timestamp_serializer",
+		skip_serializing_if = "Option::is_none"
+	)]
 	pub end: Option<Timestamp>,
 }
 
@@ -220,3 +227,27 @@ pub struct ActivityAssets {
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub small_url: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::{Activity, ActivityTimestamps};
+
+	#[test]
+	fn omits_missing_timestamps() {
+		let activity = Activity {
+			timestamps: Some(ActivityTimestamps {
+				start: Some("2025-01-01T00:00:00Z".parse().unwrap()),
+				end: None,
+			}),
+			..Default::default()
+		};
+
+		let activity = serde_json::to_value(&activity).unwrap();
+		assert_eq!(
+			activity["timestamps"],
+			json!({ "start": 1_735_689_600_000_i64 })
+		);
+	}
+}
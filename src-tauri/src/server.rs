@@ -0,0 +1,524 @@
+use std::{
+	collections::HashMap,
+	future::IntoFuture,
+	io,
+	net::SocketAddr,
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use axum::{
+	Router,
+	body::Bytes,
+	extract::{DefaultBodyLimit, Path, Query, State},
+	http::{HeaderMap, HeaderName, StatusCode, header},
+	response::{IntoResponse, Response},
+	routing::get,
+};
+use jiff::{SignedDuration, Timestamp};
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::{JoinHandle, spawn};
+use tokio::{fs, net::TcpListener, select, time::sleep};
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, warn};
+
+use crate::{api::EXPIRES_AT_HEADER, artwork::is_hash, error::AppResult, settings::ServerSettings};
+
+/// Same limits as the Cloudflare worker.
+const MAX_ARTWORK_SIZE: usize = 500 * 1024;
+pub const MAX_EXPIRY: SignedDuration = SignedDuration::from_hours(2);
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Content-addressed artwork, kept in memory and written through to disk so it survives restarts
+/// until it expires.
+#[derive(Debug, Clone, Default)]
+pub struct ArtworkStore {
+	entries: Arc<Mutex<HashMap<String, StoredArtwork>>>,
+	dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredArtwork {
+	pub mime: String,
+	pub expires_at: Timestamp,
+	#[serde(skip)]
+	pub bytes: Bytes,
+}
+
+impl ArtworkStore {
+	pub fn new(dir: Option<PathBuf>) -> Self {
+		Self {
+			entries: Default::default(),
+			dir,
+		}
+	}
+
+	#[tracing::instrument(skip(self, artwork), err)]
+	pub async fn insert(&self, hash: String, artwork: StoredArtwork) -> io::Result<()> {
+		if !is_hash(&hash) {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"artwork must be stored by its hash",
+			));
+		}
+
+		if let Some(dir) = &self.dir {
+			fs::create_dir_all(dir).await?;
+			fs::write(dir.join(&hash), &artwork.bytes).await?;
+			fs::write(
+				dir.join(format!("{hash}.json")),
+				serde_json::to_vec(&artwork)?,
+			)
+			.await?;
+		}
+
+		self.entries.lock().unwrap().insert(hash, artwork);
+		Ok(())
+	}
+
+	/// Gets unexpired artwork, falling back to disk if it isn't in memory.
+	pub async fn get(&self, hash: &str) -> Option<StoredArtwork> {
+		if !is_hash(hash) {
+			return None;
+		}
+
+		let cached = self.entries.lock().unwrap().get(hash).cloned();
+		let artwork = match cached {
+			Some(artwork) => artwork,
+			None => {
+				let artwork = self.read(hash).await?;
+				self.entries
+					.lock()
+					.unwrap()
+					.insert(hash.to_owned(), artwork.clone());
+				artwork
+			}
+		};
+
+		if artwork.expires_at <= Timestamp::now() {
+			self.remove(hash).await;
+			return None;
+		}
+
+		Some(artwork)
+	}
+
+	async fn read(&self, hash: &str) -> Option<StoredArtwork> {
+		let dir = self.dir.as_ref()?;
+		let meta = fs::read(dir.join(format!("{hash}.json"))).await.ok()?;
+		let mut artwork = serde_json::from_slice::<StoredArtwork>(&meta).ok()?;
+		artwork.bytes = fs::read(dir.join(hash)).await.ok()?.into();
+		Some(artwork)
+	}
+
+	async fn remove(&self, hash: &str) {
+		self.entries.lock().unwrap().remove(hash);
+
+		if let Some(dir) = &self.dir {
+			let _ = fs::remove_file(dir.join(hash)).await;
+			let _ = fs::remove_file(dir.join(format!("{hash}.json"))).await;
+		}
+	}
+
+	/// Removes expired artwork from memory and disk.
+	#[tracing::instrument(skip(self), level = Level::DEBUG)]
+	pub async fn evict_expired(&self) {
+		let now = Timestamp::now();
+		self.entries
+			.lock()
+			.unwrap()
+			.retain(|_, artwork| artwork.expires_at > now);
+
+		let Some(dir) = &self.dir else {
+			return;
+		};
+		let Ok(mut entries) = fs::read_dir(dir).await else {
+			return;
+		};
+		while let Ok(Some(entry)) = entries.next_entry().await {
+			let name = entry.file_name();
+			let Some(hash) = name.to_str().and_then(|name| name.strip_suffix(".json")) else {
+				continue;
+			};
+
+			let expired = match fs::read(entry.path()).await {
+				Ok(meta) => serde_json::from_slice::<StoredArtwork>(&meta)
+					.is_ok_and(|artwork| artwork.expires_at <= now),
+				Err(_) => true,
+			};
+			if expired {
+				self.remove(hash).await;
+			}
+		}
+	}
+}
+
+#[derive(Clone)]
+struct Shared {
+	store: ArtworkStore,
+	/// Hashed so it can be compared in constant time.
+	upload_token: Option<blake3::Hash>,
+}
+
+/// Serves artwork from the store with the same API as the Cloudflare worker. Uploads are only
+/// accepted with an upload token, which the worker host sends as a bearer token, since the local
+/// host writes to the store directly.
+pub fn router(store: ArtworkStore, upload_token: Option<&str>) -> Router {
+	let route = match upload_token {
+		Some(_) => get(get_artwork).put(put_artwork),
+		None => get(get_artwork),
+	};
+
+	Router::new()
+		.route("/{hash}", route)
+		.layer(DefaultBodyLimit::max(MAX_ARTWORK_SIZE))
+		.with_state(Shared {
+			store,
+			upload_token: upload_token.map(|token| blake3::hash(token.as_bytes())),
+		})
+}
+
+async fn get_artwork(State(shared): State<Shared>, Path(hash): Path<String>) -> Response {
+	let Some(artwork) = shared.store.get(&hash).await else {
+		return StatusCode::NOT_FOUND.into_response();
+	};
+
+	let max_age = (artwork.expires_at - Timestamp::now()).get_seconds().max(0);
+	(
+		[
+			(header::CONTENT_TYPE, artwork.mime),
+			(header::CACHE_CONTROL, format!("public, max-age={max_age}")),
+			(
+				HeaderName::from_static(EXPIRES_AT_HEADER),
+				artwork.expires_at.to_string(),
+			),
+		],
+		artwork.bytes,
+	)
+		.into_response()
+}
+
+#[derive(Deserialize)]
+struct PutQuery {
+	expires_at: Timestamp,
+}
+
+async fn put_artwork(
+	State(shared): State<Shared>,
+	Path(hash): Path<String>,
+	Query(query): Query<PutQuery>,
+	headers: HeaderMap,
+	bytes: Bytes,
+) -> Response {
+	let token = headers
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "));
+	let authorized = shared
+		.upload_token
+		.zip(token)
+		.is_some_and(|(expected, token)| blake3::hash(token.as_bytes()) == expected);
+	if !authorized {
+		return StatusCode::UNAUTHORIZED.into_response();
+	}
+
+	if blake3::hash(&bytes).to_hex().as_str() != hash {
+		return (
+			StatusCode::BAD_REQUEST,
+			"hash must be the blake3 hash of the body",
+		)
+			.into_response();
+	}
+
+	let now = Timestamp::now();
+	if query.expires_at <= now {
+		return (StatusCode::BAD_REQUEST, "expires_at must be in the future").into_response();
+	}
+	if query.expires_at >= now + MAX_EXPIRY {
+		return (
+			StatusCode::BAD_REQUEST,
+			format!("expires_at must be less than {}", now + MAX_EXPIRY),
+		)
+			.into_response();
+	}
+
+	let mime = headers
+		.get(header::CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.filter(|mime| mime.starts_with("image/"));
+	let Some(mime) = mime else {
+		return (StatusCode::BAD_REQUEST, "content-type must be an image").into_response();
+	};
+
+	let artwork = StoredArtwork {
+		mime: mime.to_owned(),
+		expires_at: query.expires_at,
+		bytes,
+	};
+	match shared.store.insert(hash, artwork).await {
+		Ok(()) => StatusCode::CREATED.into_response(),
+		Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+	}
+}
+
+/// The embedded artwork server, which is (re)started whenever its settings change.
+pub struct ArtworkServer {
+	store: ArtworkStore,
+	running: Option<Running>,
+}
+
+struct Running {
+	address: SocketAddr,
+	upload_token: Option<String>,
+	shutdown: CancellationToken,
+	task: JoinHandle<()>,
+}
+
+impl ArtworkServer {
+	pub fn new(store: ArtworkStore) -> Self {
+		Self {
+			store,
+			running: None,
+		}
+	}
+
+	pub fn store(&self) -> &ArtworkStore {
+		&self.store
+	}
+
+	#[tracing::instrument(skip(self), err)]
+	pub async fn apply(&mut self, settings: &ServerSettings) -> AppResult<()> {
+		let address = match settings.enabled {
+			true => Some(settings.address()?),
+			false => None,
+		};
+		let upload_token = settings.upload_token().map(ToOwned::to_owned);
+		let unchanged = match &self.running {
+			Some(running) => {
+				Some(running.address) == address && running.upload_token == upload_token
+			}
+			None => address.is_none(),
+		};
+		if unchanged {
+			return Ok(());
+		}
+
+		if let Some(running) = self.running.take() {
+			running.shutdown.cancel();
+			let _ = running.task.await;
+		}
+
+		let Some(address) = address else {
+			return Ok(());
+		};
+
+		let listener = TcpListener::bind(address).await?;
+		let shutdown = CancellationToken::new();
+		let store = self.store.clone();
+		let serve = axum::serve(listener, router(store.clone(), upload_token.as_deref()))
+			.with_graceful_shutdown(shutdown.clone().cancelled_owned());
+
+		let task = spawn(async move {
+			let sweep = async {
+				loop {
+					sleep(SWEEP_INTERVAL).await;
+					store.evict_expired().await;
+				}
+			};
+
+			select! {
+				result = serve.into_future() => {
+					if let Err(err) = result {
+						warn!(%err, "artwork server stopped");
+					}
+				}
+				_ = sweep => {}
+			}
+		});
+
+		self.running = Some(Running {
+			address,
+			upload_token,
+			shutdown,
+			task,
+		});
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use axum::{
+		body::{Body, to_bytes},
+		http::{Request, StatusCode, header},
+	};
+	use jiff::{Timestamp, ToSpan};
+	use tower::ServiceExt;
+
+	use super::{ArtworkStore, router};
+	use crate::testing::{PNG, TempDir};
+
+	const TOKEN: &str = "hunter2";
+
+	fn put(hash: &str, expires_at: Timestamp, body: &'static [u8]) -> Request<Body> {
+		Request::put(format!("/{hash}?expires_at={expires_at}"))
+			.header(header::CONTENT_TYPE, "image/png")
+			.header(header::AUTHORIZATION, format!("Bearer {TOKEN}"))
+			.body(Body::from(body))
+			.unwrap()
+	}
+
+	fn get(hash: &str) -> Request<Body> {
+		Request::get(format!("/{hash}"))
+			.body(Body::empty())
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn serves_uploaded_artwork() {
+		let app = router(ArtworkStore::default(), Some(TOKEN));
+		let hash = blake3::hash(PNG).to_hex();
+		let expires_at = Timestamp::now() + 1.hour();
+
+		let res = app
+			.clone()
+			.oneshot(put(&hash, expires_at, PNG))
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::CREATED);
+
+		let res = app.oneshot(get(&hash)).await.unwrap();
+		assert_eq!(res.status(), StatusCode::OK);
+		assert_eq!(res.headers()[header::CONTENT_TYPE], "image/png");
+		let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+		assert_eq!(&body[..], PNG);
+	}
+
+	#[tokio::test]
+	async fn rejects_mismatched_hash() {
+		let app = router(ArtworkStore::default(), Some(TOKEN));
+		let hash = blake3::hash(b"something else").to_hex();
+
+		let res = app
+			.clone()
+			.oneshot(put(&hash, Timestamp::now() + 1.hour(), PNG))
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+		let res = app.oneshot(get(&hash)).await.unwrap();
+		assert_eq!(res.status(), StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn requires_the_upload_token() {
+		let hash = blake3::hash(PNG).to_hex();
+		let expires_at = Timestamp::now() + 1.hour();
+
+		let mut unauthorized = put(&hash, expires_at, PNG);
+		unauthorized.headers_mut().remove(header::AUTHORIZATION);
+		let res = router(ArtworkStore::default(), Some(TOKEN))
+			.oneshot(unauthorized)
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+		let mut wrong = put(&hash, expires_at, PNG);
+		wrong
+			.headers_mut()
+			.insert(header::AUTHORIZATION, "Bearer hunter3".parse().unwrap());
+		let res = router(ArtworkStore::default(), Some(TOKEN))
+			.oneshot(wrong)
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+		// without a token, uploads aren't served at all
+		let res = router(ArtworkStore::default(), None)
+			.oneshot(put(&hash, expires_at, PNG))
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+	}
+
+	#[tokio::test]
+	async fn caps_the_expiry() {
+		let hash = blake3::hash(PNG).to_hex();
+		let expires_at = Timestamp::now() + 2.hours() + 1.second();
+
+		let res = router(ArtworkStore::default(), Some(TOKEN))
+			.oneshot(put(&hash, expires_at, PNG))
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn ignores_paths_that_arent_hashes() {
+		let dir = TempDir::default();
+		let served = dir.join("served-artwork");
+		let outside = dir.join("outside");
+		std::fs::create_dir_all(&served).unwrap();
+		std::fs::create_dir_all(&outside).unwrap();
+		std::fs::write(outside.join("secret"), PNG).unwrap();
+		std::fs::write(
+			outside.join("secret.json"),
+			r#"{"mime":"image/png","expires_at":"2000-01-01T00:00:00Z"}"#,
+		)
+		.unwrap();
+
+		let res = router(ArtworkStore::new(Some(served)), None)
+			.oneshot(get("..%2Foutside%2Fsecret"))
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::NOT_FOUND);
+		// expired entries outside the store aren't removed either
+		assert!(outside.join("secret").exists());
+	}
+
+	#[tokio::test]
+	async fn hides_expired_artwork() {
+		let store = ArtworkStore::default();
+		let hash = blake3::hash(PNG).to_hex().to_string();
+		store
+			.insert(
+				hash.clone(),
+				super::StoredArtwork {
+					mime: "image/png".into(),
+					expires_at: Timestamp::now() - 1.second(),
+					bytes: PNG.into(),
+				},
+			)
+			.await
+			.unwrap();
+
+		let res = router(store.clone(), None)
+			.oneshot(get(&hash))
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::NOT_FOUND);
+		assert!(store.entries.lock().unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn reads_artwork_back_from_disk() {
+		let dir = TempDir::default();
+		let hash = blake3::hash(PNG).to_hex();
+
+		let res = router(ArtworkStore::new(Some(dir.to_path_buf())), Some(TOKEN))
+			.oneshot(put(&hash, Timestamp::now() + 1.hour(), PNG))
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::CREATED);
+
+		// a fresh store has nothing in memory, so this must come from disk
+		let res = router(ArtworkStore::new(Some(dir.to_path_buf())), None)
+			.oneshot(get(&hash))
+			.await
+			.unwrap();
+		assert_eq!(res.status(), StatusCode::OK);
+		let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+		assert_eq!(&body[..], PNG);
+	}
+}
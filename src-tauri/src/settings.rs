@@ -0,0 +1,310 @@
+use std::{collections::HashMap, fmt, net::SocketAddr};
+
+use anyhow::{anyhow, ensure};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_value, to_value};
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+	api::{Api, LocalHost, MultipartHost, S3Host, WorkerHost},
+	artwork::ProcessingSettings,
+	error::AppResult,
+	media::PlayerSettings,
+	presence::PresenceSettings,
+	server::ArtworkStore,
+};
+
+/// The store shared with the frontend.
+pub const STORE_PATH: &str = "store.json";
+const SETTINGS_KEY: &str = "settings";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Settings {
+	pub artwork: ArtworkSettings,
+	pub server: ServerSettings,
+	pub players: PlayerSettings,
+	pub presence: PresenceSettings,
+}
+
+impl Settings {
+	#[tracing::instrument(skip_all, err)]
+	pub fn load<R: Runtime>(app: &AppHandle<R>) -> AppResult<Self> {
+		let store = app.store(STORE_PATH)?;
+		match store.get(SETTINGS_KEY) {
+			Some(value) => Ok(from_value(value)?),
+			None => Ok(Self::default()),
+		}
+	}
+
+	#[tracing::instrument(skip_all, err)]
+	pub fn save<R: Runtime>(&self, app: &AppHandle<R>) -> AppResult<()> {
+		let store = app.store(STORE_PATH)?;
+		store.set(SETTINGS_KEY, to_value(self)?);
+		Ok(())
+	}
+
+	/// Builds the API client for these settings, or `None` if uploads are disabled.
+	pub fn api(&self, store: &ArtworkStore) -> AppResult<Option<Api>> {
+		if !self.artwork.upload {
+			return Ok(None);
+		}
+
+		if matches!(self.artwork.host, HostSettings::Local { .. }) && !self.server.enabled {
+			return Err(
+				anyhow!("the artwork server must be enabled to host artwork locally").into(),
+			);
+		}
+
+		Ok(Some(self.artwork.host.api(store)?))
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArtworkSettings {
+	/// Whether artwork is uploaded at all. When disabled, activities are set without a large image.
+	pub upload: bool,
+	/// Where artwork is uploaded to.
+	pub host: HostSettings,
+	/// An asset key or URL shown for media without artwork, while artwork is uploading, or if the
+	/// upload fails.
+	pub fallback_image: Option<String>,
+	/// Fallback images for specific players, by player ID, used instead of `fallback_image`.
+	pub player_images: HashMap<String, String>,
+	/// How artwork is resized and re-encoded before it's uploaded.
+	pub processing: ProcessingSettings,
+}
+
+impl Default for ArtworkSettings {
+	fn default() -> Self {
+		Self {
+			upload: true,
+			host: HostSettings::default(),
+			fallback_image: None,
+			player_images: HashMap::new(),
+			processing: ProcessingSettings::default(),
+		}
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostSettings {
+	/// The Cloudflare worker in `/api`.
+	Worker {
+		/// Defaults to the URL this build was compiled with.
+		url: String,
+		/// Sent as a bearer token, for the built-in artwork server of another install.
+		#[serde(default)]
+		token: Option<String>,
+	},
+	/// An S3-compatible bucket, uploaded to with presigned URLs.
+	S3 {
+		endpoint: String,
+		bucket: String,
+		region: String,
+		access_key_id: String,
+		secret_access_key: String,
+		/// Base URL the bucket's objects are publicly readable from.
+		public_url: String,
+	},
+	/// A generic endpoint accepting `multipart/form-data` uploads.
+	Multipart {
+		upload_url: String,
+		/// Name of the form field containing the artwork.
+		field: String,
+		/// Base URL uploaded artwork is publicly readable from.
+		public_url: String,
+	},
+	/// The embedded artwork server.
+	Local {
+		/// Base URL the artwork server is publicly reachable at, e.g. through a reverse proxy.
+		public_url: String,
+	},
+}
+
+impl Default for HostSettings {
+	fn default() -> Self {
+		Self::Worker {
+			url: env!("API_URL").to_owned(),
+			token: None,
+		}
+	}
+}
+
+impl fmt::Debug for HostSettings {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Worker { url, token } => f
+				.debug_struct("Worker")
+				.field("url", url)
+				.field("token", &token.as_ref().map(|_| Redacted))
+				.finish(),
+			Self::S3 {
+				endpoint,
+				bucket,
+				region,
+				access_key_id,
+				secret_access_key: _,
+				public_url,
+			} => f
+				.debug_struct("S3")
+				.field("endpoint", endpoint)
+				.field("bucket", bucket)
+				.field("region", region)
+				.field("access_key_id", access_key_id)
+				.field("secret_access_key", &Redacted)
+				.field("public_url", public_url)
+				.finish(),
+			Self::Multipart {
+				upload_url,
+				field,
+				public_url,
+			} => f
+				.debug_struct("Multipart")
+				.field("upload_url", upload_url)
+				.field("field", field)
+				.field("public_url", public_url)
+				.finish(),
+			Self::Local { public_url } => f
+				.debug_struct("Local")
+				.field("public_url", public_url)
+				.finish(),
+		}
+	}
+}
+
+impl HostSettings {
+	pub fn api(&self, store: &ArtworkStore) -> anyhow::Result<Api> {
+		Ok(match self {
+			Self::Worker { url, token } => Api::Worker(WorkerHost::new(
+				parse_url("worker", url)?,
+				token
+					.as_deref()
+					.map(str::trim)
+					.filter(|token| !token.is_empty())
+					.map(ToOwned::to_owned),
+			)),
+			Self::S3 {
+				endpoint,
+				bucket,
+				region,
+				access_key_id,
+				secret_access_key,
+				public_url,
+			} => {
+				ensure!(!bucket.is_empty(), "S3 bucket must be set");
+				ensure!(!region.is_empty(), "S3 region must be set");
+				ensure!(
+					!access_key_id.is_empty() && !secret_access_key.is_empty(),
+					"S3 credentials must be set"
+				);
+
+				Api::S3(S3Host::new(
+					parse_url("S3 endpoint", endpoint)?,
+					bucket.clone(),
+					region.clone(),
+					access_key_id.clone(),
+					secret_access_key.clone(),
+					parse_url("public", public_url)?,
+				))
+			}
+			Self::Multipart {
+				upload_url,
+				field,
+				public_url,
+			} => {
+				ensure!(!field.is_empty(), "multipart field name must be set");
+
+				Api::Multipart(MultipartHost::new(
+					parse_url("upload", upload_url)?,
+					field.clone(),
+					parse_url("public", public_url)?,
+				))
+			}
+			Self::Local { public_url } => Api::Local(LocalHost::new(
+				store.clone(),
+				parse_url("public", public_url)?,
+			)),
+		})
+	}
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+	/// Whether to run the embedded artwork server.
+	pub enabled: bool,
+	/// The socket address the artwork server listens on.
+	pub address: String,
+	/// The bearer token other installs must send to upload artwork to this server. Uploads are
+	/// refused without one.
+	pub upload_token: Option<String>,
+}
+
+impl Default for ServerSettings {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			address: "127.0.0.1:8787".to_owned(),
+			upload_token: None,
+		}
+	}
+}
+
+impl fmt::Debug for ServerSettings {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("ServerSettings")
+			.field("enabled", &self.enabled)
+			.field("address", &self.address)
+			.field(
+				"upload_token",
+				&self.upload_token.as_ref().map(|_| Redacted),
+			)
+			.finish()
+	}
+}
+
+impl ServerSettings {
+	pub fn upload_token(&self) -> Option<&str> {
+		self.upload_token
+			.as_deref()
+			.map(str::trim)
+			.filter(|token| !token.is_empty())
+	}
+
+	pub fn address(&self) -> anyhow::Result<SocketAddr> {
+		self.address
+			.trim()
+			.parse()
+			.map_err(|err| anyhow!("invalid artwork server address: {err}"))
+	}
+}
+
+/// Debug-formatted in place of secrets, so they stay out of logs.
+pub struct Redacted;
+
+impl fmt::Debug for Redacted {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("<redacted>")
+	}
+}
+
+fn parse_url(name: &str, url: &str) -> anyhow::Result<Url> {
+	let url = Url::parse(url.trim()).map_err(|err| anyhow!("invalid {name} URL: {err}"))?;
+
+	ensure!(
+		matches!(url.scheme(), "http" | "https"),
+		"{name} URL must use http or https"
+	);
+	ensure!(url.has_host(), "{name} URL must have a host");
+	ensure!(
+		url.query().is_none() && url.fragment().is_none(),
+		"{name} URL must not have a query or fragment"
+	);
+
+	Ok(url)
+}
@@ -1,5 +1,14 @@
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock};
 
-use crate::rpc::Rpc;
+use crate::{
+	api::Api, media::PlayerFilter, presence::Presence, rpc::Rpc, server::ArtworkServer,
+	settings::Settings,
+};
 
 pub type RpcState = Mutex<Option<Rpc>>;
+/// `None` when artwork uploads are disabled.
+pub type ApiState = RwLock<Option<Api>>;
+pub type SettingsState = RwLock<Settings>;
+pub type ServerState = Mutex<ArtworkServer>;
+pub type PresenceState = Mutex<Presence>;
+pub type PlayerFilterState = RwLock<PlayerFilter>;
@@ -0,0 +1,34 @@
+//! Fixtures shared between tests.
+
+use std::{
+	ops::Deref,
+	path::{Path, PathBuf},
+};
+
+/// Sniffs as a PNG, but doesn't decode as one.
+pub const PNG: &[u8] = b"\x89PNG\r\n\x1a\nnot really a png";
+
+/// A fresh directory in the system's temp dir, removed once dropped, even if a test panics.
+pub struct TempDir(PathBuf);
+
+impl Default for TempDir {
+	fn default() -> Self {
+		let dir = std::env::temp_dir().join(format!("music-rpc-test-{}", ulid::Ulid::new()));
+		std::fs::create_dir_all(&dir).unwrap();
+		Self(dir)
+	}
+}
+
+impl Deref for TempDir {
+	type Target = Path;
+
+	fn deref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.0);
+	}
+}
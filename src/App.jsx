@@ -1,6 +1,13 @@
-import { autostartAtom, currentMediaAtom, currentAppAtom } from "./state";
+import { convertFileSrc } from "@tauri-apps/api/core";
+import {
+	autostartAtom,
+	currentMediaAtom,
+	mediaErrorAtom,
+	currentAppAtom,
+	settingsAtom,
+} from "./state";
 import { useAtom, useAtomValue } from "jotai";
-import { useId } from "react";
+import { useId, useState } from "react";
 
 export default function App() {
 	return (
@@ -9,24 +16,45 @@ export default function App() {
 				<Connection />
 			</div>
 			<div className="container">
+				<MediaError />
 				<CurrentMedia />
 				<AutostartToggle />
 			</div>
+			<div className="container">
+				<ArtworkSettings />
+			</div>
 		</div>
 	);
 }
 
+function artworkUrl(artwork) {
+	switch (artwork?.type) {
+		case "bytes":
+			return convertFileSrc(artwork.hash, "artwork");
+		case "url":
+			return artwork.url;
+		default:
+			return undefined;
+	}
+}
+
+const PLAYBACK_STATES = {
+	playing: "Playing",
+	paused: "Paused",
+	stopped: "Stopped",
+};
+
 function CurrentMedia() {
 	const media = useAtomValue(currentMediaAtom);
 	if (!media) return;
 
-	const artworkDataUrl = `data:${media.artwork_mime};base64,${media.artwork_bytes}`;
+	const artworkSrc = artworkUrl(media.artwork);
 
 	return (
 		<>
-			{media.artwork_bytes && (
+			{artworkSrc && (
 				<img
-					src={artworkDataUrl}
+					src={artworkSrc}
 					alt=""
 					style={{
 						width: "fit-content",
@@ -38,10 +66,32 @@ function CurrentMedia() {
 			)}
 			<h1 id="title">{media.title}</h1>
 			<h2 id="artist">{media.artist}</h2>
+			{media.album && <h3 id="album">{media.album}</h3>}
+			{media.player && (
+				<p id="player">
+					{PLAYBACK_STATES[media.state]}
+					{media.rate !== 1 && ` at ${media.rate}×`} in {media.player.name}
+				</p>
+			)}
 		</>
 	);
 }
 
+function MediaError() {
+	const error = useAtomValue(mediaErrorAtom);
+	if (!error) return;
+
+	const retry = error.retry_at
+		? ` Retrying at ${new Date(error.retry_at).toLocaleTimeString()}.`
+		: "";
+
+	return (
+		<p id="media-error" role="alert">
+			Media updates failed: {error.message}.{retry}
+		</p>
+	);
+}
+
 function AutostartToggle() {
 	const id = useId();
 	const [enabled, setEnabled] = useAtom(autostartAtom);
@@ -78,3 +128,239 @@ function Connection() {
 		</form>
 	);
 }
+
+const SECRET_HOST_FIELDS = new Set(["secret_access_key", "token"]);
+
+const HOST_FIELDS = {
+	worker: [
+		["url", "Worker URL"],
+		["token", "Upload token"],
+	],
+	s3: [
+		["endpoint", "Endpoint"],
+		["bucket", "Bucket"],
+		["region", "Region"],
+		["access_key_id", "Access key ID"],
+		["secret_access_key", "Secret access key"],
+		["public_url", "Public URL"],
+	],
+	multipart: [
+		["upload_url", "Upload URL"],
+		["field", "Form field"],
+		["public_url", "Public URL"],
+	],
+	local: [["public_url", "Public URL"]],
+};
+
+function ArtworkSettings() {
+	const uploadId = useId();
+	const hostTypeId = useId();
+	const fallbackImageId = useId();
+	const maxDimensionId = useId();
+	const formatId = useId();
+	const serverEnabledId = useId();
+	const serverAddressId = useId();
+	const serverTokenId = useId();
+	const allowPlayersId = useId();
+	const blockPlayersId = useId();
+	const pausedId = useId();
+	const pausedImageId = useId();
+	const idleTimeoutId = useId();
+	const [settings, setSettings] = useAtom(settingsAtom);
+	const [hostType, setHostType] = useState(settings.artwork.host.type);
+	const [error, setError] = useState();
+
+	const handleSubmit = async (event) => {
+		event.preventDefault();
+
+		const data = new FormData(event.target);
+		const host = { type: hostType };
+		for (const [name] of HOST_FIELDS[hostType]) host[name] = data.get(name);
+
+		try {
+			await setSettings({
+				...settings,
+				artwork: {
+					...settings.artwork,
+					upload: data.get("upload") === "on",
+					host,
+					fallback_image: data.get("fallbackImage") || null,
+					processing: {
+						...settings.artwork.processing,
+						max_dimension: Number(data.get("maxDimension")),
+						format: data.get("format"),
+					},
+				},
+				server: {
+					...settings.server,
+					enabled: data.get("serverEnabled") === "on",
+					address: data.get("serverAddress"),
+					upload_token: data.get("serverToken") || null,
+				},
+				players: {
+					allow: lines(data.get("allowPlayers")),
+					block: lines(data.get("blockPlayers")),
+				},
+				presence: {
+					...settings.presence,
+					paused: data.get("paused"),
+					paused_image: data.get("pausedImage") || null,
+					idle_timeout_secs: Number(data.get("idleTimeout")) * 60,
+				},
+			});
+			setError(undefined);
+		} catch (err) {
+			setError(err);
+		}
+	};
+
+	return (
+		<form onSubmit={handleSubmit}>
+			<input
+				type="checkbox"
+				id={uploadId}
+				name="upload"
+				defaultChecked={settings.artwork.upload}
+			/>
+			<label htmlFor={uploadId}>Upload artwork</label>
+			<label htmlFor={hostTypeId}>Artwork host</label>
+			<select
+				id={hostTypeId}
+				value={hostType}
+				onChange={(event) => setHostType(event.target.value)}
+			>
+				<option value="worker">Cloudflare worker</option>
+				<option value="s3">S3-compatible bucket</option>
+				<option value="multipart">Multipart upload endpoint</option>
+				<option value="local">Built-in artwork server</option>
+			</select>
+			{HOST_FIELDS[hostType].map(([name, label]) => (
+				<HostField
+					key={`${hostType}.${name}`}
+					name={name}
+					label={label}
+					defaultValue={
+						settings.artwork.host.type === hostType
+							? settings.artwork.host[name]
+							: ""
+					}
+				/>
+			))}
+			<label htmlFor={fallbackImageId}>Fallback image</label>
+			<input
+				type="text"
+				id={fallbackImageId}
+				name="fallbackImage"
+				placeholder="Asset key or URL"
+				defaultValue={settings.artwork.fallback_image ?? ""}
+			/>
+			<label htmlFor={maxDimensionId}>Max artwork size (px)</label>
+			<input
+				type="number"
+				id={maxDimensionId}
+				name="maxDimension"
+				min={64}
+				defaultValue={settings.artwork.processing.max_dimension}
+			/>
+			<label htmlFor={formatId}>Artwork format</label>
+			<select
+				id={formatId}
+				name="format"
+				defaultValue={settings.artwork.processing.format}
+			>
+				<option value="jpeg">JPEG</option>
+				<option value="png">PNG</option>
+				<option value="webp">WebP (lossless)</option>
+			</select>
+			<input
+				type="checkbox"
+				id={serverEnabledId}
+				name="serverEnabled"
+				defaultChecked={settings.server.enabled}
+			/>
+			<label htmlFor={serverEnabledId}>Run artwork server</label>
+			<label htmlFor={serverAddressId}>Artwork server address</label>
+			<input
+				type="text"
+				id={serverAddressId}
+				name="serverAddress"
+				defaultValue={settings.server.address}
+			/>
+			<label htmlFor={serverTokenId}>Artwork server upload token</label>
+			<input
+				type="password"
+				id={serverTokenId}
+				name="serverToken"
+				placeholder="Required for other installs to upload"
+				defaultValue={settings.server.upload_token ?? ""}
+			/>
+			<label htmlFor={allowPlayersId}>Only show these players</label>
+			<textarea
+				id={allowPlayersId}
+				name="allowPlayers"
+				placeholder="One player ID or name per line, e.g. com.spotify.client or Spotify*"
+				defaultValue={settings.players.allow.join("\n")}
+			/>
+			<label htmlFor={blockPlayersId}>Never show these players</label>
+			<textarea
+				id={blockPlayersId}
+				name="blockPlayers"
+				placeholder="One player ID or name per line, e.g. msedge.exe or com.google.*"
+				defaultValue={settings.players.block.join("\n")}
+			/>
+			<label htmlFor={pausedId}>When paused</label>
+			<select
+				id={pausedId}
+				name="paused"
+				defaultValue={settings.presence.paused}
+			>
+				<option value="clear">Clear presence</option>
+				<option value="show">Show as paused</option>
+				<option value="clear_when_idle">
+					Show as paused, then clear after a while
+				</option>
+			</select>
+			<label htmlFor={pausedImageId}>Paused image</label>
+			<input
+				type="text"
+				id={pausedImageId}
+				name="pausedImage"
+				placeholder="Asset key or URL"
+				defaultValue={settings.presence.paused_image ?? ""}
+			/>
+			<label htmlFor={idleTimeoutId}>Clear paused presence after (minutes)</label>
+			<input
+				type="number"
+				id={idleTimeoutId}
+				name="idleTimeout"
+				min={1}
+				defaultValue={settings.presence.idle_timeout_secs / 60}
+			/>
+			<button type="submit">Save</button>
+			{error && <p role="alert">{error}</p>}
+		</form>
+	);
+}
+
+function lines(value) {
+	return value
+		.split("\n")
+		.map((line) => line.trim())
+		.filter(Boolean);
+}
+
+function HostField({ name, label, defaultValue }) {
+	const id = useId();
+
+	return (
+		<>
+			<label htmlFor={id}>{label}</label>
+			<input
+				type={SECRET_HOST_FIELDS.has(name) ? "password" : "text"}
+				id={id}
+				name={name}
+				defaultValue={defaultValue}
+			/>
+		</>
+	);
+}
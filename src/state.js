@@ -68,11 +68,21 @@ currentMediaAtom.onMount = async (setAtom) => {
 	return unlisten;
 };
 
-observe((get) => {
-	const media = get(currentMediaAtom);
-	const isConnected = get(isConnectedAtom);
-	if (isConnected) invoke("set_activity", { media });
-});
+export const mediaErrorAtom = atom();
+mediaErrorAtom.onMount = async (setAtom) => {
+	const unlistenError = await listen("media_error", ({ payload }) => {
+		setAtom(payload);
+	});
+	// any update means media is working again
+	const unlistenMedia = await listen("media_change", () => {
+		setAtom(undefined);
+	});
+
+	return () => {
+		unlistenError();
+		unlistenMedia();
+	};
+};
 
 const autostartValueAtom = atom(isEnabled());
 export const autostartAtom = atom(
@@ -88,3 +98,14 @@ export const autostartAtom = atom(
 		set(autostartValueAtom, newValue);
 	},
 );
+
+const settingsValueAtom = atom(invoke("get_settings"));
+export const settingsAtom = atom(
+	(get) => {
+		return get(settingsValueAtom);
+	},
+	async (_get, set, settings) => {
+		await invoke("set_settings", { settings });
+		set(settingsValueAtom, settings);
+	},
+);